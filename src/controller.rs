@@ -1,56 +1,560 @@
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
+    fmt, future,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
 };
 
-use color_eyre::Result;
+use backoff::{backoff::Backoff, ExponentialBackoffBuilder};
+use clap::ValueEnum;
+use color_eyre::{eyre::eyre, Result};
 use futures::TryStreamExt;
 use k8s_openapi::api::core::v1::Namespace;
 use kube::{
     api::ListParams,
-    core::ObjectMeta,
-    runtime::{watcher, WatchStreamExt},
-    Api, Client, Resource, ResourceExt,
+    core::{ObjectMeta, PartialObjectMeta},
+    runtime::{
+        metadata_watcher,
+        reflector::{self, store::Writer, ObjectRef, Store},
+        watcher,
+    },
+    Api, Client, ResourceExt,
 };
-use tokio::task::JoinHandle;
+use tokio::{
+    sync::{watch, OnceCell},
+    task::JoinHandle,
+};
+use tracing::{debug, error, warn};
+
+/// Prefix of the namespace labels NBAM reads, while all others are dropped from the cache
+const LABEL_PREFIX: &str = "nbam-";
+
+/// Keeps only the parts of a namespace's metadata NBAM reads, i.e., its name and NBAM's labels, as clusters may have
+/// thousands of namespaces carrying large managed fields and annotations
+fn trim(meta: &mut ObjectMeta) {
+    *meta = ObjectMeta {
+        name: meta.name.take(),
+        labels: meta.labels.take().map(|labels| {
+            labels
+                .into_iter()
+                .filter(|(key, _)| key.starts_with(LABEL_PREFIX))
+                .collect()
+        }),
+        ..ObjectMeta::default()
+    };
+}
 
-pub(crate) fn run(namespaces: Arc<Mutex<HashMap<String, ObjectMeta>>>) {
-    let _handle: JoinHandle<()> = tokio::spawn({
-        let namespaces_2 = namespaces.clone();
+/// Cache of the cluster's namespaces, reflecting their creation, modification, and deletion
+#[derive(Clone)]
+pub(crate) struct NamespaceCache {
+    store: Store<PartialObjectMeta<Namespace>>,
+    /// Namespaces fetched on cache misses along with the time of fetching them, until the watcher catches up on them
+    fetched: Arc<Mutex<HashMap<String, (ObjectMeta, Instant)>>>,
+    /// Time fetched namespaces are kept for, as the watcher never catches up on ones not matching its label selector
+    fetched_ttl: Duration,
+    synced: watch::Receiver<bool>,
+}
+
+impl NamespaceCache {
+    /// Returns the metadata of the namespace, if known
+    pub(crate) fn get(&self, name: &str) -> Option<ObjectMeta> {
+        match self.store.get(&ObjectRef::new(name)) {
+            Some(namespace) => Some(namespace.metadata.clone()),
+            None => self
+                .fetched
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(name)
+                .filter(|(_, fetched_at)| fetched_at.elapsed() < self.fetched_ttl)
+                .map(|(meta, _)| meta.clone()),
+        }
+    }
 
-        async move {
-            let _: Result<()> = async move {
-                let client = Client::try_default().await?;
+    /// Whether the initial list of namespaces completed
+    pub(crate) fn is_synced(&self) -> bool {
+        *self.synced.borrow()
+    }
 
-                watcher(Api::<Namespace>::all(client), ListParams::default())
-                    .applied_objects()
-                    .try_for_each(|namespace| {
-                        let namespaces = namespaces.clone();
+    /// Caches a fetched namespace, dropping expired ones
+    fn insert_fetched(&self, meta: ObjectMeta) {
+        let mut fetched = self.fetched.lock().unwrap_or_else(PoisonError::into_inner);
+        fetched.retain(|_, (_, fetched_at)| fetched_at.elapsed() < self.fetched_ttl);
 
-                        async move {
-                            let name = namespace.name_any();
-                            let meta = namespace.meta();
+        if let Some(name) = meta.name.clone() {
+            fetched.insert(name, (meta, Instant::now()));
+        }
+    }
+
+    /// Drops fetched namespaces the watcher caught up on, as the store knows about them from now on
+    fn forget_fetched(&self, event: &watcher::Event<PartialObjectMeta<Namespace>>) {
+        let mut fetched = self.fetched.lock().unwrap_or_else(PoisonError::into_inner);
+
+        match event {
+            watcher::Event::Applied(namespace) | watcher::Event::Deleted(namespace) => {
+                fetched.remove(&namespace.name_any());
+            }
+            watcher::Event::Restarted(_) => fetched.clear(),
+        }
+    }
+}
 
-                            if let Ok(mut namespaces) = namespaces.lock() {
-                                namespaces.insert(name, meta.clone());
-                            }
+#[cfg(test)]
+impl NamespaceCache {
+    /// Builds a cache of the given namespaces, as if the watcher listed them
+    pub(crate) fn with_namespaces(namespaces: Vec<ObjectMeta>, synced: bool) -> Self {
+        Self::with_writer(namespaces, synced).0
+    }
+
+    /// Builds a cache of the given namespaces along with the writer to feed further watcher events to
+    fn with_writer(
+        namespaces: Vec<ObjectMeta>,
+        synced: bool,
+    ) -> (Self, Writer<PartialObjectMeta<Namespace>>) {
+        let (store, mut writer) = reflector::store();
+        writer.apply_watcher_event(&watcher::Event::Restarted(
+            namespaces
+                .into_iter()
+                .map(|metadata| PartialObjectMeta {
+                    types: None,
+                    metadata,
+                    _phantom: std::marker::PhantomData,
+                })
+                .collect(),
+        ));
+
+        let namespaces = NamespaceCache {
+            store,
+            fetched: Arc::default(),
+            fetched_ttl: Duration::from_secs(30),
+            synced: watch::channel(synced).1,
+        };
+
+        (namespaces, writer)
+    }
+}
+
+/// Starts watching the cluster's namespaces, returning the cache reflecting them and the watcher's task
+pub(crate) fn run(
+    max_client_failures: u32,
+    label_selector: Option<String>,
+    fetched_ttl: Duration,
+) -> (NamespaceCache, JoinHandle<()>) {
+    let (store, writer) = reflector::store();
+    let (synced_tx, synced) = watch::channel(false);
+    let namespaces = NamespaceCache {
+        store,
+        fetched: Arc::default(),
+        fetched_ttl,
+        synced,
+    };
+
+    let watcher = tokio::spawn(watch_namespaces(
+        namespaces.clone(),
+        writer,
+        synced_tx,
+        max_client_failures,
+        label_selector,
+    ));
+
+    // TODO: Implement graceful shutdown
+
+    (namespaces, watcher)
+}
+
+/// Cause of a namespace watcher failure
+enum WatcherFailure {
+    /// No client could be built, e.g., due to a missing kubeconfig
+    Client(kube::Error),
+    /// The API server rejected the client's credentials or RBAC permissions
+    Unauthorized(watcher::Error),
+    /// Any other, likely transient, failure
+    Transient(watcher::Error),
+    /// The watcher's stream ended unexpectedly
+    Ended,
+}
 
-                            Ok(())
+impl WatcherFailure {
+    fn classify(err: watcher::Error) -> Self {
+        let code = match &err {
+            watcher::Error::InitialListFailed(kube::Error::Api(response))
+            | watcher::Error::WatchStartFailed(kube::Error::Api(response))
+            | watcher::Error::WatchFailed(kube::Error::Api(response))
+            | watcher::Error::WatchError(response) => Some(response.code),
+            _ => None,
+        };
+
+        match code {
+            Some(401 | 403) => WatcherFailure::Unauthorized(err),
+            _ => WatcherFailure::Transient(err),
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self {
+            WatcherFailure::Client(_) => "client",
+            WatcherFailure::Unauthorized(_) => "unauthorized",
+            WatcherFailure::Transient(_) => "transient",
+            WatcherFailure::Ended => "ended",
+        }
+    }
+}
+
+impl fmt::Display for WatcherFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatcherFailure::Client(err) => write!(f, "could not build client: {err}"),
+            WatcherFailure::Unauthorized(err) | WatcherFailure::Transient(err) => err.fmt(f),
+            WatcherFailure::Ended => f.write_str("stream ended"),
+        }
+    }
+}
+
+/// Keeps the store up to date, restarting the watcher with a jittered exponential backoff whenever it fails, while
+/// keeping the store's contents
+async fn watch_namespaces(
+    namespaces: NamespaceCache,
+    mut writer: Writer<PartialObjectMeta<Namespace>>,
+    synced: watch::Sender<bool>,
+    max_client_failures: u32,
+    label_selector: Option<String>,
+) {
+    // Only the namespaces' metadata is watched, optionally limited to the ones matching the selector
+    let list_params = match &label_selector {
+        Some(label_selector) => ListParams::default().labels(label_selector),
+        None => ListParams::default(),
+    };
+
+    let mut backoff = ExponentialBackoffBuilder::new()
+        .with_initial_interval(Duration::from_millis(500))
+        .with_max_interval(Duration::from_secs(60))
+        .with_max_elapsed_time(None)
+        .build();
+    let mut restarts: u64 = 0;
+    let mut client_failures: u32 = 0;
+
+    loop {
+        let mut progressed = false;
+
+        let failure = match Client::try_default().await {
+            Ok(client) => {
+                client_failures = 0;
+
+                // Restarted events carry the full list, replacing the store's contents and thereby dropping deleted
+                // namespaces
+                let result = metadata_watcher(Api::<Namespace>::all(client), list_params.clone())
+                    .map_ok(|event| event.modify(|namespace| trim(&mut namespace.metadata)))
+                    .try_for_each(|event| {
+                        progressed = true;
+                        namespaces.forget_fetched(&event);
+                        writer.apply_watcher_event(&event);
+
+                        if let watcher::Event::Restarted(_) = event {
+                            synced.send_replace(true);
                         }
+
+                        future::ready(Ok(()))
                     })
-                    .await?;
+                    .await;
 
-                Ok(())
+                match result {
+                    Ok(()) => WatcherFailure::Ended,
+                    Err(err) => WatcherFailure::classify(err),
+                }
             }
-            .await;
+            Err(err) => {
+                client_failures += 1;
+                WatcherFailure::Client(err)
+            }
+        };
 
-            // TODO: Handle potential error from above closure
+        // A watcher having received events was healthy, thus failures start over with the initial interval
+        if progressed {
+            backoff.reset();
+        }
 
-            run(namespaces_2)
+        // Only failing to build clients is fatal, as the watcher recovers from any other failure
+        if matches!(failure, WatcherFailure::Client(_)) && client_failures >= max_client_failures {
+            error!(
+                reason = failure.reason(),
+                client_failures, "namespace watcher failed: {failure}, exiting"
+            );
+            std::process::exit(1);
         }
-    });
 
-    // TODO: Implement graceful shutdown
+        restarts += 1;
+        let delay = backoff.next_backoff().unwrap_or(backoff.max_interval);
+
+        warn!(
+            reason = failure.reason(),
+            restarts,
+            client_failures,
+            delay = ?delay,
+            "namespace watcher failed: {failure}, restarting"
+        );
+
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Admission of objects whose namespace is neither cached nor could be fetched from the API server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum NamespaceLookupPolicy {
+    /// Deny the object
+    Deny,
+    /// Admit the object without mutating it
+    Allow,
+}
+
+/// Fetches namespaces missing from the cache from the API server, as objects may arrive before the watcher has seen
+/// their namespace, e.g., right after starting or when applying a namespace along with its pods
+#[derive(Clone)]
+pub(crate) struct NamespaceLookup {
+    namespaces: NamespaceCache,
+    /// Built on the first cache miss, as building it may fail until the watcher succeeded doing so
+    client: Arc<OnceCell<Client>>,
+    timeout: Duration,
+    pub(crate) policy: NamespaceLookupPolicy,
+}
+
+impl NamespaceLookup {
+    pub(crate) fn new(
+        namespaces: NamespaceCache,
+        timeout: Duration,
+        policy: NamespaceLookupPolicy,
+    ) -> Self {
+        NamespaceLookup {
+            namespaces,
+            client: Arc::default(),
+            timeout,
+            policy,
+        }
+    }
+
+    /// Builds a lookup using the given client rather than the default one
+    #[cfg(test)]
+    pub(crate) fn with_client(
+        namespaces: NamespaceCache,
+        client: Client,
+        timeout: Duration,
+        policy: NamespaceLookupPolicy,
+    ) -> Self {
+        NamespaceLookup {
+            client: Arc::new(OnceCell::new_with(Some(client))),
+            ..NamespaceLookup::new(namespaces, timeout, policy)
+        }
+    }
+
+    /// Ensures the namespace is cached, fetching it on a cache miss
+    pub(crate) async fn ensure(&self, name: &str) -> Result<()> {
+        if self.namespaces.get(name).is_some() {
+            return Ok(());
+        }
+
+        debug!(
+            synced = self.namespaces.is_synced(),
+            "namespace \"{name}\" missing from cache, fetching it"
+        );
+
+        let client = self
+            .client
+            .get_or_try_init(Client::try_default)
+            .await
+            .map_err(|err| eyre!("Failed to fetch namespace \"{name}\": {err}"))?;
+        let api = Api::<Namespace>::all(client.clone());
+
+        let mut namespace = tokio::time::timeout(self.timeout, api.get_metadata(name))
+            .await
+            .map_err(|_| {
+                eyre!(
+                    "Timed out after {:?} fetching namespace \"{name}\"",
+                    self.timeout
+                )
+            })?
+            .map_err(|err| eyre!("Failed to fetch namespace \"{name}\": {err}"))?;
+
+        trim(&mut namespace.metadata);
+        self.namespaces.insert_fetched(namespace.metadata);
+
+        Ok(())
+    }
 }
 
 // TODO: Add e2e tests
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use axum::{extract::Path, http::StatusCode, routing::get, Json, Router};
+
+    use super::*;
+
+    /// Serves the given routes as a fake API server, returning a client talking to it
+    fn fake_api(app: Router) -> Client {
+        let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(app.into_make_service());
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        Client::try_from(kube::Config::new(url.parse().unwrap())).unwrap()
+    }
+
+    fn lookup(client: Client) -> NamespaceLookup {
+        NamespaceLookup::with_client(
+            NamespaceCache::with_namespaces(Vec::new(), true),
+            client,
+            Duration::from_millis(200),
+            NamespaceLookupPolicy::Deny,
+        )
+    }
+
+    /// Wraps the metadata of the namespace called `name` into a watched object
+    fn watched(name: &str) -> PartialObjectMeta<Namespace> {
+        PartialObjectMeta {
+            types: None,
+            metadata: ObjectMeta {
+                name: Some(name.to_owned()),
+                ..ObjectMeta::default()
+            },
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lookup_fetches_missing_namespace() {
+        let lookup = lookup(fake_api(Router::new().route(
+            "/api/v1/namespaces/:name",
+            get(|Path(name): Path<String>| async move {
+                Json(serde_json::json!({
+                    "apiVersion": "meta.k8s.io/v1",
+                    "kind": "PartialObjectMetadata",
+                    "metadata": {
+                        "name": name,
+                        "labels": { "nbam-mode": "strip", "team": "a" },
+                        "annotations": { "a": "b" },
+                    },
+                }))
+            }),
+        )));
+
+        assert!(lookup.namespaces.get("nbam-test").is_none());
+
+        lookup.ensure("nbam-test").await.unwrap();
+
+        // Only the name and NBAM's labels are cached
+        assert_eq!(
+            lookup.namespaces.get("nbam-test"),
+            Some(ObjectMeta {
+                name: Some("nbam-test".to_owned()),
+                labels: Some([("nbam-mode".to_owned(), "strip".to_owned())].into()),
+                ..ObjectMeta::default()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lookup_refetches_expired_namespace() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let client = fake_api(Router::new().route(
+            "/api/v1/namespaces/:name",
+            get({
+                let requests = requests.clone();
+                move |Path(name): Path<String>| async move {
+                    requests.fetch_add(1, Ordering::Relaxed);
+
+                    Json(serde_json::json!({
+                        "apiVersion": "meta.k8s.io/v1",
+                        "kind": "PartialObjectMetadata",
+                        "metadata": { "name": name },
+                    }))
+                }
+            }),
+        ));
+
+        for (ttl, expected) in [(Duration::from_secs(30), 1), (Duration::ZERO, 2)] {
+            requests.store(0, Ordering::Relaxed);
+
+            let lookup = NamespaceLookup {
+                namespaces: NamespaceCache {
+                    fetched_ttl: ttl,
+                    ..NamespaceCache::with_namespaces(Vec::new(), true)
+                },
+                ..lookup(client.clone())
+            };
+
+            lookup.ensure("nbam-test").await.unwrap();
+            lookup.ensure("nbam-test").await.unwrap();
+
+            assert_eq!(requests.load(Ordering::Relaxed), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lookup_failure() {
+        let lookup = lookup(fake_api(Router::new().route(
+            "/api/v1/namespaces/:name",
+            get(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+        )));
+
+        let err = lookup.ensure("nbam-test").await.unwrap_err();
+
+        assert!(
+            err.to_string()
+                .starts_with("Failed to fetch namespace \"nbam-test\": "),
+            "{err}"
+        );
+        assert!(lookup.namespaces.get("nbam-test").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_lookup_timeout() {
+        let lookup = lookup(fake_api(
+            Router::new().route("/api/v1/namespaces/:name", get(std::future::pending::<()>)),
+        ));
+
+        let err = lookup.ensure("nbam-test").await.unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Timed out after 200ms fetching namespace \"nbam-test\""
+        );
+    }
+
+    #[test]
+    fn test_cache_evicts_deleted_namespaces() {
+        let (namespaces, mut writer) =
+            NamespaceCache::with_writer(vec![watched("a").metadata, watched("b").metadata], true);
+
+        let event = watcher::Event::Deleted(watched("a"));
+        namespaces.forget_fetched(&event);
+        writer.apply_watcher_event(&event);
+
+        assert!(namespaces.get("a").is_none());
+        assert!(namespaces.get("b").is_some());
+
+        let event = watcher::Event::Restarted(Vec::new());
+        namespaces.forget_fetched(&event);
+        writer.apply_watcher_event(&event);
+
+        assert!(namespaces.get("b").is_none());
+    }
+
+    #[test]
+    fn test_cache_forgets_fetched_namespaces() {
+        let namespaces = NamespaceCache::with_namespaces(Vec::new(), true);
+        namespaces.insert_fetched(watched("a").metadata);
+        namespaces.insert_fetched(watched("b").metadata);
+
+        namespaces.forget_fetched(&watcher::Event::Deleted(watched("a")));
+
+        assert!(namespaces.get("a").is_none());
+        assert!(namespaces.get("b").is_some());
+
+        namespaces.forget_fetched(&watcher::Event::Restarted(Vec::new()));
+
+        assert!(namespaces.get("b").is_none());
+    }
+}
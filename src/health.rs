@@ -0,0 +1,155 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Router};
+use tokio::task::JoinHandle;
+
+use crate::controller::NamespaceCache;
+
+/// State reported by the liveness and readiness probes
+#[derive(Clone)]
+pub(crate) struct Health {
+    namespaces: NamespaceCache,
+    watcher: Arc<JoinHandle<()>>,
+    tls_loaded: Arc<AtomicBool>,
+}
+
+impl Health {
+    pub(crate) fn new(namespaces: NamespaceCache, watcher: JoinHandle<()>) -> Self {
+        Health {
+            namespaces,
+            watcher: Arc::new(watcher),
+            tls_loaded: Arc::default(),
+        }
+    }
+
+    /// Marks the TLS material as loaded, or not being required at all
+    pub(crate) fn set_tls_loaded(&self) {
+        self.tls_loaded.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Routes of the probes, served via plain HTTP, so that kubelets do not require the webhook's certificate
+pub(crate) fn router(health: Health) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(health)
+}
+
+/// Fails once the namespace watcher's task died, as the cache would not be kept up to date anymore
+async fn healthz(State(health): State<Health>) -> impl IntoResponse {
+    if health.watcher.is_finished() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "namespace watcher died");
+    }
+
+    (StatusCode::OK, "ok")
+}
+
+/// Succeeds once the namespace cache completed its initial list and the TLS material has been loaded
+async fn readyz(State(health): State<Health>) -> impl IntoResponse {
+    if health.watcher.is_finished() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "namespace watcher died");
+    }
+
+    if !health.namespaces.is_synced() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "namespace cache not synced",
+        );
+    }
+
+    if !health.tls_loaded.load(Ordering::Relaxed) {
+        return (StatusCode::SERVICE_UNAVAILABLE, "TLS material not loaded");
+    }
+
+    (StatusCode::OK, "ok")
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::HttpBody;
+
+    use super::*;
+
+    fn health(synced: bool) -> Health {
+        Health::new(
+            NamespaceCache::with_namespaces(Vec::new(), synced),
+            tokio::spawn(std::future::pending()),
+        )
+    }
+
+    async fn probe(response: impl IntoResponse) -> (StatusCode, String) {
+        let response = response.into_response();
+        let status = response.status();
+        let body = response.into_body().data().await.unwrap().unwrap();
+
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_not_synced() {
+        let health = health(false);
+        health.set_tls_loaded();
+
+        assert_eq!(
+            probe(readyz(State(health.clone())).await).await,
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "namespace cache not synced".to_owned()
+            )
+        );
+        assert_eq!(
+            probe(healthz(State(health)).await).await,
+            (StatusCode::OK, "ok".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tls_not_loaded() {
+        assert_eq!(
+            probe(readyz(State(health(true))).await).await,
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "TLS material not loaded".to_owned()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ready() {
+        let health = health(true);
+        health.set_tls_loaded();
+
+        assert_eq!(
+            probe(readyz(State(health)).await).await,
+            (StatusCode::OK, "ok".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watcher_died() {
+        let watcher = tokio::spawn(async {});
+        while !watcher.is_finished() {
+            tokio::task::yield_now().await;
+        }
+
+        let health = Health::new(NamespaceCache::with_namespaces(Vec::new(), true), watcher);
+        health.set_tls_loaded();
+
+        for response in [
+            probe(healthz(State(health.clone())).await).await,
+            probe(readyz(State(health)).await).await,
+        ] {
+            assert_eq!(
+                response,
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "namespace watcher died".to_owned()
+                )
+            );
+        }
+    }
+}
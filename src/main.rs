@@ -1,25 +1,26 @@
 #![forbid(unsafe_code)]
 mod controller;
+mod health;
 mod mutate;
 mod utils;
+mod validate;
 
-use std::{
-    collections::HashMap,
-    net::SocketAddr,
-    path::PathBuf,
-    sync::{Arc, Mutex},
-};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
 use axum::{routing::post, Router};
 use axum_server::tls_rustls::RustlsConfig;
 use clap::Parser;
 use clap_verbosity_flag::InfoLevel;
 use color_eyre::Result;
-use kube::core::ObjectMeta;
 
-use mutate::{BandwidthMode, BandwidthProps, Mode};
+use controller::{NamespaceCache, NamespaceLookup, NamespaceLookupPolicy};
+use health::Health;
+use mutate::{
+    parse_overcommit_ratio, BandwidthMode, BandwidthProps, ClampEnforcement, CniProfile,
+    ConflictPolicy, Mode, OutputFormat,
+};
 use tracing::error;
-use utils::convert_filter;
+use utils::{convert_filter, quantity::Ratio, template::PodTemplateLocator};
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
@@ -30,6 +31,9 @@ struct Cli {
     /// Socket listen address
     #[clap(long = "listen", short = 'l', env, default_value_t = SocketAddr::from(([127, 0, 0, 1], 3000)))]
     addr: SocketAddr,
+    /// Plain HTTP listen address of the liveness (/healthz) and readiness (/readyz) probes
+    #[clap(long = "probe-listen", env, default_value_t = SocketAddr::from(([127, 0, 0, 1], 8080)))]
+    probe_addr: SocketAddr,
     /// Path to PEM encoded TLS cert file
     #[clap(long, env)]
     tls_cert: Option<PathBuf>,
@@ -43,9 +47,109 @@ struct Cli {
     /// Ingress bandwidth resource key name
     #[clap(long, env, default_value_t = { "networking.k8s.io/ingress-bandwidth".to_owned() })]
     ingress_bandwidth_resource_key: String,
+    /// Deprecated egress bandwidth resource key names, still being honored
+    #[clap(
+        long = "egress-bandwidth-resource-key-alias",
+        env = "EGRESS_BANDWIDTH_RESOURCE_KEY_ALIASES",
+        value_delimiter = ','
+    )]
+    egress_bandwidth_resource_key_aliases: Vec<String>,
+    /// Deprecated ingress bandwidth resource key names, still being honored
+    #[clap(
+        long = "ingress-bandwidth-resource-key-alias",
+        env = "INGRESS_BANDWIDTH_RESOURCE_KEY_ALIASES",
+        value_delimiter = ','
+    )]
+    ingress_bandwidth_resource_key_aliases: Vec<String>,
+    /// Egress burst resource key name
+    #[clap(long, env, default_value_t = { "networking.k8s.io/egress-burst".to_owned() })]
+    egress_burst_resource_key: String,
+    /// Ingress burst resource key name
+    #[clap(long, env, default_value_t = { "networking.k8s.io/ingress-burst".to_owned() })]
+    ingress_burst_resource_key: String,
+    /// Egress packet rate resource key name
+    #[clap(long, env, default_value_t = { "networking.k8s.io/egress-packet-rate".to_owned() })]
+    egress_packet_rate_resource_key: String,
+    /// Ingress packet rate resource key name
+    #[clap(long, env, default_value_t = { "networking.k8s.io/ingress-packet-rate".to_owned() })]
+    ingress_packet_rate_resource_key: String,
+    /// Prefix of the per-network bandwidth resource keys, written as PREFIX<network>-egress or PREFIX<network>-ingress
+    #[clap(long, env, default_value_t = { "bandwidth.nbam.io/".to_owned() })]
+    network_bandwidth_resource_prefix: String,
+    /// Format of the written bandwidth annotations, overridable using the "nbam-output-format" namespace label
+    #[clap(long, env, value_enum, default_value_t = OutputFormat::Canonical)]
+    output_format: OutputFormat,
+    /// CNI shaping the pods' traffic, deciding the written limit annotations, overridable using the "nbam-cni-profile"
+    /// namespace label
+    #[clap(long, env, value_enum, default_value_t = CniProfile::BandwidthPlugin)]
+    cni_profile: CniProfile,
+    /// Policy for bandwidth annotations a pod already carries, overridable using the "nbam-conflict-policy" namespace label
+    #[clap(long, env, value_enum, default_value_t = ConflictPolicy::Overwrite)]
+    conflict_policy: ConflictPolicy,
+    /// Factor the ratio mode derives limits from requests with, overridable using the "nbam-overcommit-ratio" namespace label
+    #[clap(long, env, value_parser = parse_overcommit_ratio, default_value = "2")]
+    overcommit_ratio: Ratio,
+    /// Enforcement of the namespaces' bandwidth bounds, overridable using the "nbam-clamp-enforcement" namespace label
+    #[clap(long, env, value_enum, default_value_t = ClampEnforcement::Rewrite)]
+    clamp_enforcement: ClampEnforcement,
+    /// Pod template locations of custom resources as GROUP/VERSION/KIND=SPEC_POINTER:METADATA_POINTER, e.g.,
+    /// "argoproj.io/v1alpha1/Rollout=/spec/template/spec:/spec/template/metadata"
+    #[clap(
+        long = "pod-template-locator",
+        env = "POD_TEMPLATE_LOCATORS",
+        value_delimiter = ','
+    )]
+    pod_template_locators: Vec<PodTemplateLocator>,
+    /// Label selector limiting the watched namespaces, e.g., "nbam-mode" for the ones having a mode label, while
+    /// other namespaces are fetched on demand
+    #[clap(long, env)]
+    namespace_label_selector: Option<String>,
+    /// Consecutive failures to build a Kubernetes client after which the namespace watcher exits the process
+    #[clap(long, env, default_value_t = 10)]
+    max_client_failures: u32,
+    /// Timeout in milliseconds of fetching a namespace missing from the cache from the API server
+    #[clap(long, env, default_value_t = 2000)]
+    namespace_lookup_timeout: u64,
+    /// Time in milliseconds namespaces fetched from the API server are cached for, unless the namespace watcher
+    /// catches up on them earlier
+    #[clap(long, env, default_value_t = 30000)]
+    namespace_lookup_ttl: u64,
+    /// Admission of objects whose namespace is neither cached nor could be fetched from the API server
+    #[clap(long, env, value_enum, default_value_t = NamespaceLookupPolicy::Deny)]
+    namespace_lookup_policy: NamespaceLookupPolicy,
+    /// Deny pods with malformed, non-positive, or contradicting bandwidth resources on the mutating endpoints
+    #[clap(long, env)]
+    strict: bool,
 }
 
-pub(crate) type NamespaceCache = Arc<Mutex<HashMap<String, ObjectMeta>>>;
+impl Cli {
+    fn bandwidth_props(&self, mode: BandwidthMode, namespaces: &NamespaceCache) -> BandwidthProps {
+        BandwidthProps {
+            egress_bandwidth_resource_key: self.egress_bandwidth_resource_key.clone(),
+            ingress_bandwidth_resource_key: self.ingress_bandwidth_resource_key.clone(),
+            egress_bandwidth_resource_key_aliases: self
+                .egress_bandwidth_resource_key_aliases
+                .clone(),
+            ingress_bandwidth_resource_key_aliases: self
+                .ingress_bandwidth_resource_key_aliases
+                .clone(),
+            egress_burst_resource_key: self.egress_burst_resource_key.clone(),
+            ingress_burst_resource_key: self.ingress_burst_resource_key.clone(),
+            egress_packet_rate_resource_key: self.egress_packet_rate_resource_key.clone(),
+            ingress_packet_rate_resource_key: self.ingress_packet_rate_resource_key.clone(),
+            network_bandwidth_resource_prefix: self.network_bandwidth_resource_prefix.clone(),
+            mode,
+            output_format: self.output_format,
+            cni_profile: self.cni_profile,
+            conflict_policy: self.conflict_policy,
+            clamp_enforcement: self.clamp_enforcement,
+            overcommit_ratio: self.overcommit_ratio,
+            pod_template_locators: self.pod_template_locators.clone(),
+            strict: self.strict,
+            namespaces: namespaces.clone(),
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -57,70 +161,104 @@ async fn main() -> Result<()> {
         .with_max_level(convert_filter(cli.verbose.log_level_filter()))
         .init();
 
-    let namespaces: NamespaceCache = Arc::new(Mutex::new(HashMap::new()));
+    let (namespaces, watcher) = controller::run(
+        cli.max_client_failures,
+        cli.namespace_label_selector.clone(),
+        Duration::from_millis(cli.namespace_lookup_ttl),
+    );
+
+    // Probes are served right away, as liveness doesn't depend on the webhook being served
+    let health = Health::new(namespaces.clone(), watcher);
 
-    controller::run(namespaces.clone());
+    tokio::spawn({
+        let app = health::router(health.clone());
+        let addr = cli.probe_addr;
+
+        async move {
+            tracing::debug!("probes listening on {}", &addr);
+
+            if let Err(err) = axum_server::bind(addr).serve(app.into_make_service()).await {
+                error!("Could not serve probes: {err:?}");
+            }
+        }
+    });
+
+    let lookup = NamespaceLookup::new(
+        namespaces.clone(),
+        Duration::from_millis(cli.namespace_lookup_timeout),
+        cli.namespace_lookup_policy,
+    );
 
     let app = Router::new()
+        .route(
+            "/mutate",
+            post({
+                let props = cli.bandwidth_props(BandwidthMode::Annotate, &namespaces);
+                let lookup = lookup.clone();
+                move |body| mutate::handler(body, Mode::Mutate(props), lookup)
+            }),
+        )
         .route(
             "/annotate",
             post({
-                let egress_bandwidth_resource_key = cli.egress_bandwidth_resource_key.clone();
-                let ingress_bandwidth_resource_key = cli.ingress_bandwidth_resource_key.clone();
-
-                move |body| {
-                    mutate::handler(
-                        body,
-                        Mode::Bandwidth(BandwidthProps {
-                            egress_bandwidth_resource_key,
-                            ingress_bandwidth_resource_key,
-                            mode: BandwidthMode::Annotate,
-                        }),
-                    )
-                }
+                let props = cli.bandwidth_props(BandwidthMode::Annotate, &namespaces);
+                let lookup = lookup.clone();
+                move |body| mutate::handler(body, Mode::Bandwidth(props), lookup)
             }),
         )
         .route(
             "/strip",
             post({
-                let egress_bandwidth_resource_key = cli.egress_bandwidth_resource_key.clone();
-                let ingress_bandwidth_resource_key = cli.ingress_bandwidth_resource_key.clone();
-
-                move |body| {
-                    mutate::handler(
-                        body,
-                        Mode::Bandwidth(BandwidthProps {
-                            egress_bandwidth_resource_key,
-                            ingress_bandwidth_resource_key,
-                            mode: BandwidthMode::Strip,
-                        }),
-                    )
-                }
+                let props = cli.bandwidth_props(BandwidthMode::Strip, &namespaces);
+                let lookup = lookup.clone();
+                move |body| mutate::handler(body, Mode::Bandwidth(props), lookup)
             }),
         )
         .route(
             "/overwrite",
             post({
-                let egress_bandwidth_resource_key = cli.egress_bandwidth_resource_key.clone();
-                let ingress_bandwidth_resource_key = cli.ingress_bandwidth_resource_key.clone();
-
-                move |body| {
-                    mutate::handler(
-                        body,
-                        Mode::Bandwidth(BandwidthProps {
-                            egress_bandwidth_resource_key,
-                            ingress_bandwidth_resource_key,
-                            mode: BandwidthMode::Overwrite,
-                        }),
-                    )
-                }
+                let props = cli.bandwidth_props(BandwidthMode::Overwrite, &namespaces);
+                let lookup = lookup.clone();
+                move |body| mutate::handler(body, Mode::Bandwidth(props), lookup)
+            }),
+        )
+        .route(
+            "/ratio",
+            post({
+                let props = cli.bandwidth_props(BandwidthMode::Ratio, &namespaces);
+                let lookup = lookup.clone();
+                move |body| mutate::handler(body, Mode::Bandwidth(props), lookup)
+            }),
+        )
+        .route(
+            "/fill-missing",
+            post({
+                let props = cli.bandwidth_props(BandwidthMode::FillMissing, &namespaces);
+                let lookup = lookup.clone();
+                move |body| mutate::handler(body, Mode::Bandwidth(props), lookup)
+            }),
+        )
+        .route(
+            "/validate",
+            post({
+                let props = cli.bandwidth_props(BandwidthMode::Annotate, &namespaces);
+                let lookup = lookup.clone();
+                move |body| mutate::handler(body, Mode::Validation(props), lookup)
             }),
         )
         .route(
             "/namespace",
-            post(move |body| mutate::handler(body, Mode::Scheduler(namespaces.clone()))),
+            post(move |body| {
+                mutate::handler(
+                    body,
+                    Mode::Scheduler(namespaces.clone(), cli.pod_template_locators.clone()),
+                    lookup.clone(),
+                )
+            }),
         );
 
+    let tls_configured = cli.tls_cert.is_some() || cli.tls_key.is_some();
+
     let config: Option<RustlsConfig> = if let Some(tls_cert_file) = cli.tls_cert {
         if let Some(tls_key_file) = cli.tls_key {
             // TODO: Implement certificate rotation logic
@@ -139,6 +277,11 @@ async fn main() -> Result<()> {
         None
     };
 
+    // Without a configured certificate, there is no TLS material to wait for
+    if config.is_some() || !tls_configured {
+        health.set_tls_loaded();
+    }
+
     // TODO: Handle graceful shutdown
 
     if let Some(config) = config {
@@ -1,41 +1,332 @@
+use std::collections::BTreeMap;
+
 use axum::{http::StatusCode, response::IntoResponse, Json};
-use color_eyre::{eyre::ContextCompat, Result};
-use json_patch::{AddOperation, CopyOperation, PatchOperation, RemoveOperation};
+use clap::ValueEnum;
+use color_eyre::{
+    eyre::{eyre, ContextCompat, WrapErr},
+    Result,
+};
+use json_patch::{AddOperation, CopyOperation, PatchOperation, RemoveOperation, ReplaceOperation};
 use kube::{
     core::{
         admission::{AdmissionRequest, AdmissionResponse, AdmissionReview},
         DynamicObject,
     },
-    Resource, ResourceExt,
+    ResourceExt,
 };
 use tracing::{error, info, warn};
 
 use crate::{
-    utils::{escape_json_pointer, quantity},
-    NamespaceCache,
+    controller::{NamespaceCache, NamespaceLookup, NamespaceLookupPolicy},
+    utils::{
+        escape_json_pointer,
+        networks::{bandwidth_entry, network_resource, parse_networks, NETWORKS_ANNOTATION},
+        quantity::{self, Format, Quantity, QuantityError, Ratio},
+        resources::{effective, ContainerKind},
+        template::{PodTemplate, PodTemplateLocator},
+    },
+    validate::validate_bandwidth,
 };
 
 pub(crate) enum Mode {
     Bandwidth(BandwidthProps),
-    Scheduler(NamespaceCache),
+    /// Resolves the bandwidth mode and default scheduler from the pod's or its namespace's labels, ignoring the
+    /// props' bandwidth mode
+    Mutate(BandwidthProps),
+    Scheduler(NamespaceCache, Vec<PodTemplateLocator>),
+    /// Validates the bandwidth resources without mutating the pod, ignoring the bandwidth mode
+    Validation(BandwidthProps),
 }
 
+#[derive(Clone)]
 pub(crate) struct BandwidthProps {
     pub(crate) egress_bandwidth_resource_key: String,
     pub(crate) ingress_bandwidth_resource_key: String,
+    /// Deprecated egress bandwidth resource keys, still being honored
+    pub(crate) egress_bandwidth_resource_key_aliases: Vec<String>,
+    /// Deprecated ingress bandwidth resource keys, still being honored
+    pub(crate) ingress_bandwidth_resource_key_aliases: Vec<String>,
+    pub(crate) egress_burst_resource_key: String,
+    pub(crate) ingress_burst_resource_key: String,
+    pub(crate) egress_packet_rate_resource_key: String,
+    pub(crate) ingress_packet_rate_resource_key: String,
+    pub(crate) network_bandwidth_resource_prefix: String,
     pub(crate) mode: BandwidthMode,
+    pub(crate) output_format: OutputFormat,
+    pub(crate) cni_profile: CniProfile,
+    pub(crate) conflict_policy: ConflictPolicy,
+    pub(crate) clamp_enforcement: ClampEnforcement,
+    /// Factor the ratio mode scales requests by
+    pub(crate) overcommit_ratio: Ratio,
+    /// Pod template locations of custom resources
+    pub(crate) pod_template_locators: Vec<PodTemplateLocator>,
+    /// Deny pods with malformed, non-positive, or contradicting bandwidth resources
+    pub(crate) strict: bool,
+    pub(crate) namespaces: NamespaceCache,
+}
+
+impl BandwidthProps {
+    /// Egress and ingress bandwidth resource keys, each followed by its deprecated aliases
+    pub(crate) fn bandwidth_resource_keys(&self) -> [Vec<&str>; 2] {
+        [
+            (
+                &self.egress_bandwidth_resource_key,
+                &self.egress_bandwidth_resource_key_aliases,
+            ),
+            (
+                &self.ingress_bandwidth_resource_key,
+                &self.ingress_bandwidth_resource_key_aliases,
+            ),
+        ]
+        .map(|(key, aliases)| {
+            std::iter::once(key.as_str())
+                .chain(aliases.iter().map(String::as_str))
+                .collect()
+        })
+    }
 }
 
+#[cfg(test)]
+impl BandwidthProps {
+    /// Props of the given mode using the CLI's defaults
+    pub(crate) fn with_defaults(mode: BandwidthMode, namespaces: NamespaceCache) -> Self {
+        BandwidthProps {
+            egress_bandwidth_resource_key: "networking.k8s.io/egress-bandwidth".to_owned(),
+            ingress_bandwidth_resource_key: "networking.k8s.io/ingress-bandwidth".to_owned(),
+            egress_bandwidth_resource_key_aliases: Vec::new(),
+            ingress_bandwidth_resource_key_aliases: Vec::new(),
+            egress_burst_resource_key: "networking.k8s.io/egress-burst".to_owned(),
+            ingress_burst_resource_key: "networking.k8s.io/ingress-burst".to_owned(),
+            egress_packet_rate_resource_key: "networking.k8s.io/egress-packet-rate".to_owned(),
+            ingress_packet_rate_resource_key: "networking.k8s.io/ingress-packet-rate".to_owned(),
+            network_bandwidth_resource_prefix: "bandwidth.nbam.io/".to_owned(),
+            mode,
+            output_format: OutputFormat::Canonical,
+            cni_profile: CniProfile::BandwidthPlugin,
+            conflict_policy: ConflictPolicy::Overwrite,
+            clamp_enforcement: ClampEnforcement::Rewrite,
+            overcommit_ratio: Ratio::new(2, 1),
+            pod_template_locators: Vec::new(),
+            strict: false,
+            namespaces,
+        }
+    }
+}
+
+/// Pod and namespace label selecting the bandwidth mode on the `/mutate` endpoint
+const MODE_LABEL: &str = "nbam-mode";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub(crate) enum BandwidthMode {
     Annotate,
     Strip,
     Overwrite,
+    /// Overwrites limits with requests, while deriving the limit annotations from the requests scaled by a factor
+    Ratio,
+    /// Fills missing requests from limits and vice versa
+    FillMissing,
+}
+
+/// Namespace label overriding the factor of the ratio mode
+const OVERCOMMIT_RATIO_LABEL: &str = "nbam-overcommit-ratio";
+
+/// Parses the factor limits are derived from requests with in the ratio mode
+pub(crate) fn parse_overcommit_ratio(value: &str) -> Result<Ratio, String> {
+    let ratio: Ratio = value.parse().map_err(|err| format!("{err}"))?;
+
+    if !ratio.is_positive() {
+        return Err(format!("must be positive, got {value}"));
+    }
+
+    Ok(ratio)
+}
+
+/// Namespace labels declaring the bandwidth of containers not declaring any, like a LimitRange's defaults
+const DEFAULT_EGRESS_REQUEST_LABEL: &str = "nbam-default-egress-request";
+const DEFAULT_INGRESS_REQUEST_LABEL: &str = "nbam-default-ingress-request";
+const DEFAULT_EGRESS_LIMIT_LABEL: &str = "nbam-default-egress-limit";
+const DEFAULT_INGRESS_LIMIT_LABEL: &str = "nbam-default-ingress-limit";
+
+/// Annotation recording the namespace defaults injected into a pod's containers
+const DEFAULTS_ANNOTATION: &str = "nbam-defaults";
+
+/// Namespace labels bounding the bandwidth of containers and pods, applying to requests and limits alike
+const MIN_EGRESS_LABEL: &str = "nbam-min-egress-bandwidth";
+const MAX_EGRESS_LABEL: &str = "nbam-max-egress-bandwidth";
+const MIN_INGRESS_LABEL: &str = "nbam-min-ingress-bandwidth";
+const MAX_INGRESS_LABEL: &str = "nbam-max-ingress-bandwidth";
+
+/// Namespace label overriding the enforcement of bandwidth bounds
+const CLAMP_ENFORCEMENT_LABEL: &str = "nbam-clamp-enforcement";
+
+/// Annotation recording the original values of clamped bandwidths
+const CLAMPED_ANNOTATION: &str = "nbam-clamped";
+
+/// How values outside of a namespace's bandwidth bounds are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ClampEnforcement {
+    /// Rewrite the values to the nearest bound
+    Rewrite,
+    /// Deny the pod
+    Deny,
+}
+
+/// Namespace label overriding the output format of bandwidth annotations
+const OUTPUT_FORMAT_LABEL: &str = "nbam-output-format";
+
+/// The unit and format bandwidth annotations are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    /// Canonical quantity, keeping the format used in the pod's resources, e.g., 2M or 2Mi
+    Canonical,
+    /// Binary SI quantity, wherever the value is a whole multiple of 1Ki, e.g., 2Mi
+    BinarySi,
+    /// Plain integer of bits per second, e.g., 2000000
+    Bps,
+    /// Plain integer of Mbit/s, rounded up, e.g., 2
+    Mbps,
+}
+
+impl OutputFormat {
+    pub(crate) fn format(self, quantity: Quantity) -> String {
+        match self {
+            OutputFormat::Canonical => quantity.to_string(),
+            OutputFormat::BinarySi => quantity.with_format(Format::BinarySI).to_string(),
+            OutputFormat::Bps => quantity.scaled_value(0).to_string(),
+            OutputFormat::Mbps => quantity.scaled_value(6).to_string(),
+        }
+    }
+
+    /// Reads back a value written in this format
+    pub(crate) fn parse(self, value: &str) -> Result<Quantity, QuantityError> {
+        match self {
+            OutputFormat::Canonical | OutputFormat::BinarySi | OutputFormat::Bps => {
+                quantity::parse(value)
+            }
+            OutputFormat::Mbps => quantity::parse(value)? * Ratio::new(1_000_000, 1),
+        }
+    }
+}
+
+/// Resources shaped by some CNIs besides the bandwidth rates, which are aggregated like limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ExtraResource {
+    EgressBurst,
+    IngressBurst,
+    EgressPacketRate,
+    IngressPacketRate,
+}
+
+impl ExtraResource {
+    pub(crate) const ALL: [ExtraResource; 4] = [
+        ExtraResource::EgressBurst,
+        ExtraResource::IngressBurst,
+        ExtraResource::EgressPacketRate,
+        ExtraResource::IngressPacketRate,
+    ];
+
+    fn description(self) -> &'static str {
+        match self {
+            ExtraResource::EgressBurst => "egress burst",
+            ExtraResource::IngressBurst => "ingress burst",
+            ExtraResource::EgressPacketRate => "egress packet rate",
+            ExtraResource::IngressPacketRate => "ingress packet rate",
+        }
+    }
+
+    pub(crate) fn resource_key(self, props: &BandwidthProps) -> &str {
+        match self {
+            ExtraResource::EgressBurst => &props.egress_burst_resource_key,
+            ExtraResource::IngressBurst => &props.ingress_burst_resource_key,
+            ExtraResource::EgressPacketRate => &props.egress_packet_rate_resource_key,
+            ExtraResource::IngressPacketRate => &props.ingress_packet_rate_resource_key,
+        }
+    }
+}
+
+/// Namespace label overriding the CNI profile
+const CNI_PROFILE_LABEL: &str = "nbam-cni-profile";
+
+/// The CNI shaping the pods' traffic, deciding which limit annotations are written in which unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum CniProfile {
+    /// The bandwidth CNI plugin's kubernetes.io/egress-bandwidth and kubernetes.io/ingress-bandwidth annotations
+    BandwidthPlugin,
+    /// Same annotations as the bandwidth plugin
+    Calico,
+    /// Only honors the kubernetes.io/egress-bandwidth annotation
+    Cilium,
+    /// ovn.kubernetes.io/egress_rate and ovn.kubernetes.io/ingress_rate annotations in Mbit/s
+    KubeOvn,
+    /// Same annotations as the bandwidth plugin
+    Antrea,
+}
+
+impl CniProfile {
+    /// Annotations the CNI reads the egress and ingress limits from, `None` for unsupported directions
+    pub(crate) fn limit_annotations(self) -> (Option<&'static str>, Option<&'static str>) {
+        match self {
+            CniProfile::BandwidthPlugin | CniProfile::Calico | CniProfile::Antrea => (
+                Some("kubernetes.io/egress-bandwidth"),
+                Some("kubernetes.io/ingress-bandwidth"),
+            ),
+            CniProfile::Cilium => (Some("kubernetes.io/egress-bandwidth"), None),
+            CniProfile::KubeOvn => (
+                Some("ovn.kubernetes.io/egress_rate"),
+                Some("ovn.kubernetes.io/ingress_rate"),
+            ),
+        }
+    }
+
+    /// Annotation the CNI reads the extra resource from, `None` if unsupported
+    pub(crate) fn extra_annotation(self, resource: ExtraResource) -> Option<&'static str> {
+        match (self, resource) {
+            (CniProfile::Calico, ExtraResource::EgressBurst) => {
+                Some("qos.projectcalico.org/egressBurst")
+            }
+            (CniProfile::Calico, ExtraResource::IngressBurst) => {
+                Some("qos.projectcalico.org/ingressBurst")
+            }
+            (CniProfile::Calico, ExtraResource::EgressPacketRate) => {
+                Some("qos.projectcalico.org/egressPacketRate")
+            }
+            (CniProfile::Calico, ExtraResource::IngressPacketRate) => {
+                Some("qos.projectcalico.org/ingressPacketRate")
+            }
+            _ => None,
+        }
+    }
+
+    /// Format of the limit annotations, overriding the configured one for CNIs only understanding a single unit
+    pub(crate) fn output_format(self, configured: OutputFormat) -> OutputFormat {
+        match self {
+            CniProfile::KubeOvn => OutputFormat::Mbps,
+            _ => configured,
+        }
+    }
+}
+
+/// Namespace label overriding the policy for pre-existing bandwidth annotations
+const CONFLICT_POLICY_LABEL: &str = "nbam-conflict-policy";
+
+/// How to resolve a conflict between a pod's pre-existing annotation and the computed value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ConflictPolicy {
+    /// Keep the existing annotation
+    RespectExisting,
+    /// Replace the existing annotation with the computed value
+    Overwrite,
+    /// Use the smaller of the existing and the computed value
+    TakeMin,
+    /// Deny the pod
+    Deny,
 }
 
 // A general /mutate handler, handling errors from the underlying business logic
 pub(crate) async fn handler(
     Json(body): Json<AdmissionReview<DynamicObject>>,
     mode: Mode,
+    lookup: NamespaceLookup,
 ) -> impl IntoResponse {
     // Parse incoming webhook AdmissionRequest first
     let req: AdmissionRequest<_> = match body.try_into() {
@@ -56,20 +347,54 @@ pub(crate) async fn handler(
     if let Some(obj) = req.object {
         let name = obj.name_any(); // apiserver may not have generated a name yet
 
-        res = match match mode {
-            Mode::Bandwidth(props) => mutate_bandwidth(
-                res.clone(),
-                &obj,
-                &props.egress_bandwidth_resource_key,
-                &props.ingress_bandwidth_resource_key,
-                props.mode,
-            ),
-            Mode::Scheduler(cache) => mutate_scheduler(res.clone(), &obj, cache),
+        let dependency = namespace_dependency(&mode, &obj);
+        let cached = match (dependency, obj.namespace()) {
+            (NamespaceDependency::None, _) | (_, None) => Ok(()),
+            (_, Some(namespace)) => lookup.ensure(&namespace).await,
+        };
+
+        // Namespaces unknown to the cache lack any overrides, thus the deployment-wide settings apply
+        let mut fallback = None;
+        let cached = match cached {
+            Err(err) if dependency == NamespaceDependency::Optional => {
+                fallback = Some(format!(
+                    "{err}, thus the deployment-wide settings were applied"
+                ));
+
+                Ok(())
+            }
+            cached => cached,
+        };
+
+        res = match match cached {
+            Err(err) if lookup.policy == NamespaceLookupPolicy::Allow => {
+                let mut res = res.clone();
+                res.warnings = Some(vec![format!(
+                    "{err}, thus the object was admitted without mutation"
+                )]);
+
+                Ok(res)
+            }
+            Err(err) => Err(err),
+            Ok(()) => match mode {
+                Mode::Bandwidth(props) => {
+                    mutate_bandwidth(res.clone(), &obj, req.old_object.as_ref(), &props)
+                }
+                Mode::Mutate(props) => mutate(res.clone(), &obj, req.old_object.as_ref(), &props),
+                Mode::Scheduler(cache, locators) => {
+                    mutate_scheduler(res.clone(), &obj, cache, &locators)
+                }
+                Mode::Validation(props) => validate_bandwidth(&obj, &props).map(|()| res.clone()),
+            },
         } {
-            Ok(res) => {
+            Ok(mut res) => {
                 // TODO: Remove those verbose logs
                 info!("accepted: {:?} on pod {}", req.operation, name);
 
+                if let Some(fallback) = fallback {
+                    res.warnings.get_or_insert_with(Vec::new).push(fallback);
+                }
+
                 res
             }
             Err(err) => {
@@ -83,229 +408,712 @@ pub(crate) async fn handler(
     (StatusCode::OK, Json(res.into_review()))
 }
 
+/// How an object's admission depends on the labels of its namespace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NamespaceDependency {
+    /// The labels aren't read at all
+    None,
+    /// The labels merely override deployment-wide settings
+    Optional,
+    /// The labels decide the mutation, e.g., the scheduler of pods not picking one themselves
+    Required,
+}
+
+fn namespace_dependency(mode: &Mode, obj: &DynamicObject) -> NamespaceDependency {
+    // Labels the mode falls back to the namespace's ones for
+    let (locators, labels): (&[PodTemplateLocator], &[&str]) = match mode {
+        Mode::Validation(_) => return NamespaceDependency::None,
+        Mode::Bandwidth(props) => (&props.pod_template_locators, &[]),
+        Mode::Mutate(props) => (&props.pod_template_locators, &[MODE_LABEL, SCHEDULER_LABEL]),
+        Mode::Scheduler(_, locators) => (locators, &[SCHEDULER_LABEL]),
+    };
+
+    // Objects without a pod template are left untouched
+    if PodTemplate::locate(obj, locators).is_none() {
+        return NamespaceDependency::None;
+    }
+
+    match mode {
+        _ if labels
+            .iter()
+            .any(|key| template_label(obj, locators, key).is_none()) =>
+        {
+            NamespaceDependency::Required
+        }
+        Mode::Scheduler(..) => NamespaceDependency::None,
+        _ => NamespaceDependency::Optional,
+    }
+}
+
+/// Runs the bandwidth mode and the scheduler override selected by the pod's labels, falling back to its
+/// namespace's ones, combining their patches, where workloads' pods inherit the labels of their template
+fn mutate(
+    res: AdmissionResponse,
+    obj: &DynamicObject,
+    old: Option<&DynamicObject>,
+    props: &BandwidthProps,
+) -> Result<AdmissionResponse> {
+    let mut patches = Vec::new();
+    let mut warnings = Vec::new();
+
+    let locators = &props.pod_template_locators;
+
+    if let Some(mode) = template_or_namespace_label(&props.namespaces, obj, locators, MODE_LABEL)? {
+        let mode = BandwidthMode::from_str(&mode, false)
+            .map_err(|err| eyre!("Invalid \"{MODE_LABEL}\" label: {err}"))?;
+
+        let (bandwidth_patches, bandwidth_warnings) = bandwidth_patches(
+            obj,
+            old,
+            &BandwidthProps {
+                mode,
+                ..props.clone()
+            },
+        )?;
+        patches.extend(bandwidth_patches);
+        warnings.extend(bandwidth_warnings);
+    }
+
+    if let Some(scheduler_name) =
+        template_or_namespace_label(&props.namespaces, obj, locators, SCHEDULER_LABEL)?
+    {
+        let (scheduler_patches, scheduler_warnings) =
+            scheduler_patches(obj, scheduler_name, locators);
+        patches.extend(scheduler_patches);
+        warnings.extend(scheduler_warnings);
+    }
+
+    respond(res, patches, warnings)
+}
+
 // The main handler and core business logic, failures here implies rejected applies
 fn mutate_bandwidth(
     res: AdmissionResponse,
     obj: &DynamicObject,
-    egress_bandwidth_resource_key: &str,
-    ingress_bandwidth_resource_key: &str,
-    mode: BandwidthMode,
+    old: Option<&DynamicObject>,
+    props: &BandwidthProps,
 ) -> Result<AdmissionResponse> {
+    let (patches, warnings) = bandwidth_patches(obj, old, props)?;
+
+    respond(res, patches, warnings)
+}
+
+/// Computes the patches and warnings of the props' bandwidth mode
+fn bandwidth_patches(
+    obj: &DynamicObject,
+    old: Option<&DynamicObject>,
+    props: &BandwidthProps,
+) -> Result<(Vec<PatchOperation>, Vec<String>)> {
+    // Objects without a pod template are left untouched
+    let Some(template) = PodTemplate::locate(obj, &props.pod_template_locators) else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+    let metadata_pointer = template.metadata_pointer();
+    let spec_pointer = template.spec_pointer();
+    let annotations = template.annotations();
+
+    // Objects created by a controller from an already mutated template, e.g., a Deployment's ReplicaSets and their
+    // pods, inherit its mutations, as do updates leaving the template's spec alone, which not all modes could apply
+    // twice without losing the original limits, thus they are merely checked
+    let old_template = old
+        .and_then(|old| PodTemplate::locate(old, &props.pod_template_locators))
+        .filter(|old_template| old_template.annotations().contains_key("nba-admission"));
+    let inherited = annotations.contains_key("nba-admission")
+        && (!obj.owner_references().is_empty()
+            || old_template
+                .as_ref()
+                .is_some_and(|old_template| old_template.spec() == template.spec()));
+
+    // Annotations an update left as the previous mutation wrote them are recomputed rather than conflicting
+    let previous = old_template
+        .map(|old_template| old_template.annotations())
+        .unwrap_or_default();
+    let owned = |annotation: &str| {
+        annotations
+            .get(annotation)
+            .is_some_and(|value| previous.get(annotation) == Some(value))
+    };
+
+    if props.strict {
+        validate_bandwidth(obj, props)?;
+    }
+
+    // Namespaces may override the deployment-wide settings
+    let output_format = namespace_setting(
+        &props.namespaces,
+        obj,
+        OUTPUT_FORMAT_LABEL,
+        props.output_format,
+    )?;
+    let conflict_policy = namespace_setting(
+        &props.namespaces,
+        obj,
+        CONFLICT_POLICY_LABEL,
+        props.conflict_policy,
+    )?;
+    let cni_profile =
+        namespace_setting(&props.namespaces, obj, CNI_PROFILE_LABEL, props.cni_profile)?;
+
+    // Namespaces may override the ratio mode's factor as well
+    let ratio_props;
+    let props = if let BandwidthMode::Ratio = props.mode {
+        let overcommit_ratio =
+            match namespace_label(&props.namespaces, obj, OVERCOMMIT_RATIO_LABEL)? {
+                Some(value) => parse_overcommit_ratio(&value).map_err(|err| {
+                    eyre!("Invalid \"{OVERCOMMIT_RATIO_LABEL}\" label on namespace: {err}")
+                })?,
+                None => props.overcommit_ratio,
+            };
+
+        ratio_props = BandwidthProps {
+            overcommit_ratio,
+            ..props.clone()
+        };
+        &ratio_props
+    } else {
+        props
+    };
+
     let mut patches = Vec::new();
+    let mut warnings = Vec::new();
 
-    // If the resource doesn't contain "admission", we add it to the resource.
-    if !obj.annotations().contains_key("nba-admission") {
-        // Ensure annotations exist before adding a key to it
-        if obj.meta().annotations.is_none() {
-            patches.push(PatchOperation::Add(AddOperation {
-                path: "/metadata/annotations".into(),
-                value: serde_json::json!({}),
-            }));
-        }
+    // Ensure the (template's) metadata and annotations exist before adding our annotation to it
+    if !template.has_metadata() {
+        patches.push(PatchOperation::Add(AddOperation {
+            path: metadata_pointer.to_owned(),
+            value: serde_json::json!({}),
+        }));
+    }
 
-        // Add our annotation
+    if !template.has_annotations() {
         patches.push(PatchOperation::Add(AddOperation {
-            path: "/metadata/annotations/nba-admission".into(),
-            value: serde_json::Value::String("true".into()),
+            path: format!("{metadata_pointer}/annotations"),
+            value: serde_json::json!({}),
         }));
+    }
+
+    // Add our annotation
+    patches.push(PatchOperation::Add(AddOperation {
+        path: format!("{metadata_pointer}/annotations/nba-admission"),
+        value: serde_json::Value::String("true".into()),
+    }));
+
+    // Inherited templates were defaulted already, unless their values were stripped
+    let defaults = if inherited {
+        BandwidthDefaults::default()
+    } else {
+        namespace_defaults(&props.namespaces, obj, &mut warnings)?
     };
+    let mut injected = Vec::new();
 
-    let mut egress_request: Option<f64> = None;
-    let mut ingress_request: Option<f64> = None;
-    let mut egress_limit: Option<f64> = None;
-    let mut ingress_limit: Option<f64> = None;
+    let clamp_enforcement = namespace_setting(
+        &props.namespaces,
+        obj,
+        CLAMP_ENFORCEMENT_LABEL,
+        props.clamp_enforcement,
+    )?;
+    let egress_bounds =
+        namespace_bounds(&props.namespaces, obj, MIN_EGRESS_LABEL, MAX_EGRESS_LABEL)?;
+    let ingress_bounds =
+        namespace_bounds(&props.namespaces, obj, MIN_INGRESS_LABEL, MAX_INGRESS_LABEL)?;
+    let mut clamped = Vec::new();
 
-    if let Some(containers) = obj.data.get("spec").and_then(|spec| {
-        spec.get("containers")
+    let spec = template.spec();
+
+    let mut egress_requests = Vec::new();
+    let mut ingress_requests = Vec::new();
+    let mut egress_limits = Vec::new();
+    let mut ingress_limits = Vec::new();
+    let mut extras: [Vec<_>; 4] = Default::default();
+    let mut networks = BTreeMap::<_, Vec<_>>::new();
+
+    // Init containers come first, as their declaration order matters for the effective resources
+    for field in ["initContainers", "containers"] {
+        let Some(containers) = spec
+            .and_then(|spec| spec.get(field))
             .and_then(|containers| containers.as_array())
-    }) {
+        else {
+            continue;
+        };
+
         for (index, container) in containers.iter().enumerate() {
-            let Some(resources) = container.get("resources") else { continue };
-
-            // -- Get egress and ingress requests --
-            let requests = resources.get("requests").map(|requests| {
-                (
-                    requests
-                        .get(egress_bandwidth_resource_key)
-                        .and_then(|bandwidth| bandwidth.as_str())
-                        .map(quantity::parse),
-                    requests
-                        .get(ingress_bandwidth_resource_key)
-                        .and_then(|bandwidth| bandwidth.as_str())
-                        .map(quantity::parse),
-                )
-            });
+            let kind = if field == "containers" {
+                ContainerKind::App
+            } else if container
+                .get("restartPolicy")
+                .and_then(|restart_policy| restart_policy.as_str())
+                == Some("Always")
+            {
+                ContainerKind::Sidecar
+            } else {
+                ContainerKind::Init
+            };
 
-            if let Some((egress, ingress)) = &requests {
-                if let Some(Ok(egress)) = egress {
-                    if egress_request.is_none() {
-                        egress_request = Some(0.0);
-                    }
+            let name = container
+                .get("name")
+                .and_then(|name| name.as_str())
+                .unwrap_or_default();
 
-                    if let Some(egress_request) = egress_request.as_mut() {
-                        *egress_request += egress;
-                    }
+            // Rewrites of clamped values have to precede the mode's patches, e.g., the copies of the overwrite mode
+            let start = patches.len();
+            let path = format!("{spec_pointer}/{field}/{index}/resources");
+
+            let (requests, limits) = match container.get("resources") {
+                Some(resources) => mutate_resources(
+                    resources,
+                    &path,
+                    &format!("container \"{name}\""),
+                    props,
+                    &mut patches,
+                    &mut warnings,
+                ),
+                None => (None, None),
+            };
+
+            if let Some(resources) = container.get("resources") {
+                let values = mutate_extra_resources(
+                    resources,
+                    &path,
+                    &format!("container \"{name}\""),
+                    props,
+                    &mut patches,
+                    &mut warnings,
+                );
+
+                for (extra, value) in extras.iter_mut().zip(values) {
+                    extra.push((kind, value));
                 }
 
-                if let Some(Ok(ingress)) = ingress {
-                    if ingress_request.is_none() {
-                        ingress_request = Some(0.0);
-                    }
+                let values = mutate_network_resources(
+                    resources,
+                    &path,
+                    &format!("container \"{name}\""),
+                    props,
+                    &mut patches,
+                    &mut warnings,
+                );
 
-                    if let Some(ingress_request) = ingress_request.as_mut() {
-                        *ingress_request += ingress;
-                    }
+                for (network, value) in values {
+                    networks
+                        .entry(network)
+                        .or_default()
+                        .push((kind, Some(value)));
                 }
             }
 
-            // -- Get egress and ingress limits --
-            let limits = resources.get("limits").map(|limits| {
-                (
-                    limits
-                        .get(egress_bandwidth_resource_key)
-                        .and_then(|bandwidth| bandwidth.as_str())
-                        .map(quantity::parse),
-                    limits
-                        .get(ingress_bandwidth_resource_key)
-                        .and_then(|bandwidth| bandwidth.as_str())
-                        .map(quantity::parse),
-                )
-            });
+            let (egress_request, ingress_request) = requests.unwrap_or_default();
+            let (egress_limit, ingress_limit) = limits.unwrap_or_default();
 
-            if let Some((egress, ingress)) = &limits {
-                if let Some(Ok(egress)) = egress {
-                    if egress_limit.is_none() {
-                        egress_limit = Some(0.0);
+            let mut defaulted = Vec::new();
+            let mut originals = Vec::new();
+            let mut rewrites = Vec::new();
+            let mut resolve = |value: Option<Result<Quantity, QuantityError>>,
+                               default: Option<Quantity>,
+                               bounds: &BandwidthBounds,
+                               description: &str,
+                               pointer: Option<String>|
+             -> Result<Option<Quantity>> {
+                // Absent values fall back to the namespace's defaults, while malformed ones stay ignored
+                let (value, pointer) = match (value, default) {
+                    // Unlike malformed values, summed up or scaled ones exceeding the representable range are fatal
+                    (Some(Err(err @ QuantityError::Overflow)), _) => {
+                        return Err(err).wrap_err_with(|| {
+                            format!(
+                                "{}{} overflowed",
+                                description[..1].to_uppercase(),
+                                &description[1..]
+                            )
+                        });
+                    }
+                    (Some(value), _) => (value.ok(), pointer),
+                    (None, Some(default)) => {
+                        defaulted.push(format!("{description} {default}"));
+                        (Some(default), None)
                     }
+                    (None, None) => (None, None),
+                };
+                let Some(value) = value else {
+                    return Ok(None);
+                };
+
+                let bounded = bounds.clamp(
+                    clamp_enforcement,
+                    &format!("{description} of container \"{name}\""),
+                    value,
+                )?;
+
+                if bounded != value {
+                    originals.push(format!("{description} {value}"));
 
-                    if let Some(egress_limit) = egress_limit.as_mut() {
-                        *egress_limit += egress;
+                    // Defaulted and derived values only exist in the annotations, thus there is nothing to rewrite
+                    if let Some(pointer) = pointer {
+                        rewrites.push(PatchOperation::Replace(ReplaceOperation {
+                            path: pointer,
+                            value: serde_json::Value::String(bounded.to_string()),
+                        }));
                     }
                 }
 
-                if let Some(Ok(ingress)) = ingress {
-                    if ingress_limit.is_none() {
-                        ingress_limit = Some(0.0);
-                    }
+                Ok(Some(bounded))
+            };
 
-                    if let Some(ingress_limit) = ingress_limit.as_mut() {
-                        *ingress_limit += ingress;
+            // Only values the container declares using a single key can be rewritten, unlike filled, derived, or
+            // summed up ones
+            let derived_limits = props.mode == BandwidthMode::Ratio;
+            let [egress_keys, ingress_keys] = props.bandwidth_resource_keys();
+            let pointer = |list: &str, keys: &[&str]| {
+                let mut declared = keys.iter().filter(|key| {
+                    container
+                        .get("resources")
+                        .and_then(|resources| resources.get(list))
+                        .and_then(|list| list.get(key))
+                        .is_some()
+                });
+
+                match (declared.next(), declared.next()) {
+                    (Some(key), None) if !(derived_limits && list == "limits") => {
+                        Some(format!("{path}/{list}/{}", escape_json_pointer(key)))
                     }
+                    _ => None,
                 }
+            };
+
+            egress_requests.push((
+                kind,
+                resolve(
+                    egress_request,
+                    defaults.egress_request,
+                    &egress_bounds,
+                    "egress request",
+                    pointer("requests", &egress_keys),
+                )?,
+            ));
+            ingress_requests.push((
+                kind,
+                resolve(
+                    ingress_request,
+                    defaults.ingress_request,
+                    &ingress_bounds,
+                    "ingress request",
+                    pointer("requests", &ingress_keys),
+                )?,
+            ));
+            egress_limits.push((
+                kind,
+                resolve(
+                    egress_limit,
+                    defaults.egress_limit,
+                    &egress_bounds,
+                    "egress limit",
+                    pointer("limits", &egress_keys),
+                )?,
+            ));
+            ingress_limits.push((
+                kind,
+                resolve(
+                    ingress_limit,
+                    defaults.ingress_limit,
+                    &ingress_bounds,
+                    "ingress limit",
+                    pointer("limits", &ingress_keys),
+                )?,
+            ));
+
+            patches.splice(start..start, rewrites);
+
+            if !defaulted.is_empty() {
+                injected.push(format!("container {name}: {}", defaulted.join(", ")));
             }
 
-            // -- Mutation modes --
-            match mode {
-                BandwidthMode::Annotate => {
-                    // In annotate mode, no further operations have to be performed on the Kubernetes object
-                    // thus it's a noop
-                }
-                // Strip custom bandwidth resource requests and limits if strip = true
-                BandwidthMode::Strip => {
-                    if let Some((egress, ingress)) = requests {
-                        if let Some(Ok(_)) = egress {
-                            patches.push(PatchOperation::Remove(RemoveOperation {
-                                path: format!(
-                                    "/spec/containers/{index}/resources/requests/{}",
-                                    escape_json_pointer(egress_bandwidth_resource_key)
-                                ),
-                            }));
-                        }
+            if !originals.is_empty() {
+                clamped.push(format!("container {name}: {}", originals.join(", ")));
+            }
+        }
+    }
 
-                        if let Some(Ok(_)) = ingress {
-                            patches.push(PatchOperation::Remove(RemoveOperation {
-                                path: format!(
-                                    "/spec/containers/{index}/resources/requests/{}",
-                                    escape_json_pointer(ingress_bandwidth_resource_key)
-                                ),
-                            }));
-                        }
-                    }
+    let mut egress_request = effective(egress_requests).wrap_err("Egress request overflowed")?;
+    let mut ingress_request = effective(ingress_requests).wrap_err("Ingress request overflowed")?;
+    let mut egress_limit = effective(egress_limits).wrap_err("Egress limit overflowed")?;
+    let mut ingress_limit = effective(ingress_limits).wrap_err("Ingress limit overflowed")?;
+    let mut extras = ExtraResource::ALL
+        .into_iter()
+        .zip(extras)
+        .map(|(resource, values)| {
+            effective(values).wrap_err_with(|| format!("{} overflowed", resource.description()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut networks = networks
+        .into_iter()
+        .map(|((network, direction), values)| {
+            let value = effective(values).wrap_err_with(|| {
+                format!("{direction} bandwidth of network {network} overflowed")
+            })?;
 
-                    if let Some((egress, ingress)) = limits {
-                        if let Some(Ok(_)) = egress {
-                            patches.push(PatchOperation::Remove(RemoveOperation {
-                                path: format!(
-                                    "/spec/containers/{index}/resources/limits/{}",
-                                    escape_json_pointer(egress_bandwidth_resource_key)
-                                ),
-                            }));
-                        }
+            Ok(((network, direction), value))
+        })
+        .collect::<Result<BTreeMap<_, _>>>()?;
 
-                        if let Some(Ok(_)) = ingress {
-                            patches.push(PatchOperation::Remove(RemoveOperation {
-                                path: format!(
-                                    "/spec/containers/{index}/resources/limits/{}",
-                                    escape_json_pointer(ingress_bandwidth_resource_key)
-                                ),
-                            }));
-                        }
-                    }
+    // Pod-level resources take precedence over the containers' ones
+    if let Some(resources) = spec.and_then(|spec| spec.get("resources")) {
+        let (requests, limits) = mutate_resources(
+            resources,
+            &format!("{spec_pointer}/resources"),
+            "pod resources",
+            props,
+            &mut patches,
+            &mut warnings,
+        );
+
+        if let Some((egress, ingress)) = requests {
+            egress_request = egress.and_then(Result::ok).or(egress_request);
+            ingress_request = ingress.and_then(Result::ok).or(ingress_request);
+        }
+
+        if let Some((egress, ingress)) = limits {
+            egress_limit = egress.and_then(Result::ok).or(egress_limit);
+            ingress_limit = ingress.and_then(Result::ok).or(ingress_limit);
+        }
+
+        let values = mutate_extra_resources(
+            resources,
+            &format!("{spec_pointer}/resources"),
+            "pod resources",
+            props,
+            &mut patches,
+            &mut warnings,
+        );
+
+        for (extra, value) in extras.iter_mut().zip(values) {
+            *extra = value.or(*extra);
+        }
+
+        let values = mutate_network_resources(
+            resources,
+            &format!("{spec_pointer}/resources"),
+            "pod resources",
+            props,
+            &mut patches,
+            &mut warnings,
+        );
+
+        for (network, value) in values {
+            networks.insert(network, Some(value));
+        }
+    }
+
+    // The RuntimeClass' overhead is added on top of requests, and limits if there are any, just like the kubelet does
+    if let Some(overhead) = spec.and_then(|spec| spec.get("overhead")) {
+        let (egress, ingress) = bandwidth(overhead, props);
+
+        for (direction, quantity) in [("egress", &egress), ("ingress", &ingress)] {
+            if let Some(Err(err)) = quantity {
+                warnings.push(format!(
+                    "pod overhead: ignored malformed {direction} bandwidth: {err}"
+                ));
+            }
+        }
+
+        if let Some(Ok(egress)) = egress {
+            egress_request = Some(match egress_request {
+                Some(egress_request) => {
+                    (egress_request + egress).wrap_err("Egress request overflowed")?
                 }
-                BandwidthMode::Overwrite => {
-                    // Check if current container has both, requests and limits set
-                    if let (Some(limits), Some(requests)) = (limits, requests) {
-                        // Check if current container has egress bandwidth defined in both requests and limits set
-                        if let (Some(Ok(_)), Some(Ok(_))) = (limits.0, requests.0) {
-                            patches.push(PatchOperation::Copy(CopyOperation {
-                                from: format!(
-                                    "/spec/containers/{index}/resources/requests/{}",
-                                    escape_json_pointer(egress_bandwidth_resource_key)
-                                ),
-                                path: format!(
-                                    "/spec/containers/{index}/resources/limits/{}",
-                                    escape_json_pointer(egress_bandwidth_resource_key)
-                                ),
-                            }));
-                        }
+                None => egress,
+            });
 
-                        // Check if current container has ingress bandwidth defined in both requests and limits set
-                        if let (Some(Ok(_)), Some(Ok(_))) = (limits.1, requests.1) {
-                            patches.push(PatchOperation::Copy(CopyOperation {
-                                from: format!(
-                                    "/spec/containers/{index}/resources/requests/{}",
-                                    escape_json_pointer(ingress_bandwidth_resource_key)
-                                ),
-                                path: format!(
-                                    "/spec/containers/{index}/resources/limits/{}",
-                                    escape_json_pointer(ingress_bandwidth_resource_key)
-                                ),
-                            }));
-                        }
-                    }
+            if let Some(limit) = egress_limit {
+                egress_limit = Some((limit + egress).wrap_err("Egress limit overflowed")?);
+            }
+        }
+
+        if let Some(Ok(ingress)) = ingress {
+            ingress_request = Some(match ingress_request {
+                Some(ingress_request) => {
+                    (ingress_request + ingress).wrap_err("Ingress request overflowed")?
                 }
-            };
+                None => ingress,
+            });
+
+            if let Some(limit) = ingress_limit {
+                ingress_limit = Some((limit + ingress).wrap_err("Ingress limit overflowed")?);
+            }
         }
     }
 
-    // Add request annotations for use-cases with dedicated schedulers
-    if let Some(egress_request) = egress_request {
-        patches.push(PatchOperation::Add(AddOperation {
-            path: "/metadata/annotations/kubernetes.io~1egress-request".into(),
-            value: serde_json::Value::String(egress_request.to_string()),
-        }));
+    // The pod as a whole has to stay within the bounds as well
+    let mut originals = Vec::new();
+    for (value, bounds, description) in [
+        (&mut egress_request, &egress_bounds, "egress request"),
+        (&mut ingress_request, &ingress_bounds, "ingress request"),
+        (&mut egress_limit, &egress_bounds, "egress limit"),
+        (&mut ingress_limit, &ingress_bounds, "ingress limit"),
+    ] {
+        let Some(quantity) = *value else {
+            continue;
+        };
+
+        let bounded = bounds.clamp(
+            clamp_enforcement,
+            &format!("{description} of pod"),
+            quantity,
+        )?;
+
+        if bounded != quantity {
+            originals.push(format!("{description} {quantity}"));
+            *value = Some(bounded);
+        }
     }
-    if let Some(ingress_request) = ingress_request {
-        patches.push(PatchOperation::Add(AddOperation {
-            path: "/metadata/annotations/kubernetes.io~1ingress-request".into(),
-            value: serde_json::Value::String(ingress_request.to_string()),
-        }));
+
+    if !originals.is_empty() {
+        clamped.push(format!("pod: {}", originals.join(", ")));
     }
 
-    // Add annotations to pod if limits exist
-    if let Some(egress_limit) = egress_limit {
-        patches.push(PatchOperation::Add(AddOperation {
-            path: "/metadata/annotations/kubernetes.io~1egress-bandwidth".into(),
-            value: serde_json::Value::String(egress_limit.to_string()),
-        }));
+    if inherited {
+        return Ok((Vec::new(), Vec::new()));
+    }
+
+    // CNIs do not shape pods using the host's network namespace
+    if (egress_limit.is_some() || ingress_limit.is_some())
+        && spec
+            .and_then(|spec| spec.get("hostNetwork"))
+            .and_then(|host_network| host_network.as_bool())
+            == Some(true)
+    {
+        warnings.push(
+            "pod uses the host network, thus CNIs will not apply its bandwidth annotations"
+                .to_owned(),
+        );
     }
-    if let Some(ingress_limit) = ingress_limit {
+
+    // Request annotations for use-cases with dedicated schedulers
+    let mut targets = vec![
+        (
+            "kubernetes.io/egress-request",
+            egress_request,
+            output_format,
+        ),
+        (
+            "kubernetes.io/ingress-request",
+            ingress_request,
+            output_format,
+        ),
+    ];
+
+    // Limit annotations used by the CNI for traffic shaping
+    let (egress_annotation, ingress_annotation) = cni_profile.limit_annotations();
+    let limit_format = cni_profile.output_format(output_format);
+
+    for (direction, annotation, value) in [
+        ("egress", egress_annotation, egress_limit),
+        ("ingress", ingress_annotation, ingress_limit),
+    ] {
+        match (annotation, value) {
+            (Some(annotation), value) => targets.push((annotation, value, limit_format)),
+            (None, Some(_)) => warnings.push(format!(
+                "the {} CNI profile does not support {direction} bandwidth limits, thus they will not be shaped",
+                cni_profile
+                    .to_possible_value()
+                    .map(|value| value.get_name().to_owned())
+                    .unwrap_or_default()
+            )),
+            (None, None) => {}
+        }
+    }
+
+    // Extra resources are only written for CNIs supporting them
+    for (resource, value) in ExtraResource::ALL.into_iter().zip(extras) {
+        match (cni_profile.extra_annotation(resource), value) {
+            (Some(annotation), value) => {
+                targets.push((annotation, value, OutputFormat::Canonical));
+            }
+            (None, Some(_)) => warnings.push(format!(
+                "the {} CNI profile does not support the {}, thus it will not be applied",
+                cni_profile
+                    .to_possible_value()
+                    .map(|value| value.get_name().to_owned())
+                    .unwrap_or_default(),
+                resource.description()
+            )),
+            (None, None) => {}
+        }
+    }
+
+    for (annotation, value, output_format) in targets {
+        let owned = owned(annotation);
+        let Some(value) = value else {
+            // Annotations of values an update removed would be stale otherwise
+            if owned {
+                patches.push(PatchOperation::Remove(RemoveOperation {
+                    path: format!(
+                        "{metadata_pointer}/annotations/{}",
+                        escape_json_pointer(annotation)
+                    ),
+                }));
+            }
+
+            continue;
+        };
+
+        let Some(value) = resolve_conflict(
+            conflict_policy,
+            output_format,
+            annotation,
+            annotations.get(annotation).filter(|_| !owned),
+            value,
+            &mut warnings,
+        )?
+        else {
+            continue;
+        };
+
         patches.push(PatchOperation::Add(AddOperation {
-            path: "/metadata/annotations/kubernetes.io~1ingress-bandwidth".into(),
-            value: serde_json::Value::String(ingress_limit.to_string()),
+            path: format!(
+                "{metadata_pointer}/annotations/{}",
+                escape_json_pointer(annotation)
+            ),
+            value: serde_json::Value::String(output_format.format(value)),
         }));
     }
 
+    // Secondary networks are shaped through the bandwidth runtime config of their Multus network selection elements
+    if let Some(patch) = network_patch(&annotations, networks, metadata_pointer, &mut warnings) {
+        patches.push(patch);
+    }
+
+    // Record the injected defaults and the original values of clamped bandwidths for auditing purposes, dropping
+    // the records of a previous mutation that no longer apply
+    for (annotation, records) in [
+        (DEFAULTS_ANNOTATION, injected),
+        (CLAMPED_ANNOTATION, clamped),
+    ] {
+        let path = format!(
+            "{metadata_pointer}/annotations/{}",
+            escape_json_pointer(annotation)
+        );
+
+        if !records.is_empty() {
+            patches.push(PatchOperation::Add(AddOperation {
+                path,
+                value: serde_json::Value::String(records.join("; ")),
+            }));
+        } else if owned(annotation) {
+            patches.push(PatchOperation::Remove(RemoveOperation { path }));
+        }
+    }
+
+    Ok((patches, warnings))
+}
+
+/// Attaches the patches and warnings to the admission response
+fn respond(
+    mut res: AdmissionResponse,
+    patches: Vec<PatchOperation>,
+    warnings: Vec<String>,
+) -> Result<AdmissionResponse> {
+    if !warnings.is_empty() {
+        res.warnings = Some(warnings);
+    }
+
     Ok(if !patches.is_empty() {
         res.with_patch(json_patch::Patch(patches))?
     } else {
@@ -313,18 +1121,633 @@ fn mutate_bandwidth(
     })
 }
 
-fn mutate_scheduler(
-    res: AdmissionResponse,
+/// Default bandwidth requests and limits of a namespace
+#[derive(Debug, Default)]
+struct BandwidthDefaults {
+    egress_request: Option<Quantity>,
+    ingress_request: Option<Quantity>,
+    egress_limit: Option<Quantity>,
+    ingress_limit: Option<Quantity>,
+}
+
+/// Reads the default bandwidth from the labels of the object's namespace, ignoring malformed ones
+fn namespace_defaults(
+    namespaces: &NamespaceCache,
+    obj: &DynamicObject,
+    warnings: &mut Vec<String>,
+) -> Result<BandwidthDefaults> {
+    let mut default = |label: &str| -> Result<Option<Quantity>> {
+        let Some(value) = namespace_label(namespaces, obj, label)? else {
+            return Ok(None);
+        };
+
+        Ok(match quantity::parse(&value) {
+            Ok(quantity) => Some(quantity),
+            Err(err) => {
+                warnings.push(format!(
+                    "ignored malformed \"{label}\" label on namespace: {err}"
+                ));
+                None
+            }
+        })
+    };
+
+    let egress_limit = default(DEFAULT_EGRESS_LIMIT_LABEL)?;
+    let ingress_limit = default(DEFAULT_INGRESS_LIMIT_LABEL)?;
+
+    // Just like a LimitRange, the default requests fall back to the default limits
+    Ok(BandwidthDefaults {
+        egress_request: default(DEFAULT_EGRESS_REQUEST_LABEL)?.or(egress_limit),
+        ingress_request: default(DEFAULT_INGRESS_REQUEST_LABEL)?.or(ingress_limit),
+        egress_limit,
+        ingress_limit,
+    })
+}
+
+/// Lower and upper bound of a single bandwidth direction
+#[derive(Debug, Default)]
+struct BandwidthBounds {
+    min: Option<Quantity>,
+    max: Option<Quantity>,
+}
+
+impl BandwidthBounds {
+    /// Clamps the value into the bounds, or fails for out-of-range values if they are to be denied
+    fn clamp(
+        &self,
+        enforcement: ClampEnforcement,
+        subject: &str,
+        value: Quantity,
+    ) -> Result<Quantity> {
+        let (bound, violation) = match (self.min, self.max) {
+            (Some(min), _) if value < min => (min, "is below the namespace's minimum"),
+            (_, Some(max)) if value > max => (max, "exceeds the namespace's maximum"),
+            _ => return Ok(value),
+        };
+
+        match enforcement {
+            ClampEnforcement::Rewrite => Ok(bound),
+            ClampEnforcement::Deny => Err(eyre!("The {subject} ({value}) {violation} of {bound}")),
+        }
+    }
+}
+
+/// Reads the bandwidth bounds from the labels of the object's namespace
+fn namespace_bounds(
+    namespaces: &NamespaceCache,
+    obj: &DynamicObject,
+    min_label: &str,
+    max_label: &str,
+) -> Result<BandwidthBounds> {
+    let bound = |label: &str| -> Result<Option<Quantity>> {
+        namespace_label(namespaces, obj, label)?
+            .map(|value| {
+                quantity::parse(&value)
+                    .map_err(|err| eyre!("Invalid \"{label}\" label on namespace: {err}"))
+            })
+            .transpose()
+    };
+
+    let bounds = BandwidthBounds {
+        min: bound(min_label)?,
+        max: bound(max_label)?,
+    };
+
+    if let (Some(min), Some(max)) = (bounds.min, bounds.max) {
+        if min > max {
+            return Err(eyre!(
+                "Invalid \"{min_label}\" label on namespace: {min} exceeds \"{max_label}\" ({max})"
+            ));
+        }
+    }
+
+    Ok(bounds)
+}
+
+/// Resolves a conflict between an existing annotation and its computed value, returning the value to write, if any
+fn resolve_conflict(
+    policy: ConflictPolicy,
+    output_format: OutputFormat,
+    annotation: &str,
+    existing: Option<&String>,
+    computed: Quantity,
+    warnings: &mut Vec<String>,
+) -> Result<Option<Quantity>> {
+    let Some(existing) = existing else {
+        return Ok(Some(computed));
+    };
+
+    let parsed = output_format.parse(existing);
+    let formatted = output_format.format(computed);
+
+    // Annotations matching the computed value, e.g., ones written on creation, are no conflict
+    if parsed.as_ref() == Ok(&computed) || *existing == formatted {
+        return Ok(Some(computed));
+    }
+
+    match policy {
+        ConflictPolicy::RespectExisting => {
+            warnings.push(format!(
+                "kept existing {annotation} annotation {existing:?} instead of the computed value {formatted:?}"
+            ));
+
+            Ok(None)
+        }
+        ConflictPolicy::Overwrite => {
+            warnings.push(format!(
+                "replaced existing {annotation} annotation {existing:?} with the computed value {formatted:?}"
+            ));
+
+            Ok(Some(computed))
+        }
+        ConflictPolicy::TakeMin => match parsed {
+            Ok(parsed) if parsed < computed => {
+                warnings.push(format!(
+                    "kept existing {annotation} annotation {existing:?}, as it is lower than the computed value {formatted:?}"
+                ));
+
+                Ok(None)
+            }
+            Ok(_) => {
+                warnings.push(format!(
+                    "replaced existing {annotation} annotation {existing:?} with the lower computed value {formatted:?}"
+                ));
+
+                Ok(Some(computed))
+            }
+            Err(err) => {
+                warnings.push(format!(
+                    "replaced existing {annotation} annotation {existing:?} with the computed value {formatted:?}, as the existing one is invalid: {err}"
+                ));
+
+                Ok(Some(computed))
+            }
+        },
+        ConflictPolicy::Deny => Err(eyre!(
+            "Annotation \"{annotation}\" is already set to {existing:?}, conflicting with the computed value {formatted:?}"
+        )),
+    }
+}
+
+/// Egress and ingress bandwidth declared in a resource list, if any
+pub(crate) type Bandwidth = (
+    Option<Result<Quantity, QuantityError>>,
+    Option<Result<Quantity, QuantityError>>,
+);
+
+/// Parses the egress and ingress bandwidth from a resource list, such as a container's requests or limits,
+/// summing up the values of all keys of a direction
+pub(crate) fn bandwidth(resource_list: &serde_json::Value, props: &BandwidthProps) -> Bandwidth {
+    let [egress_keys, ingress_keys] = props.bandwidth_resource_keys();
+
+    (
+        aggregate_bandwidth(&declared_bandwidth(resource_list, &egress_keys)),
+        aggregate_bandwidth(&declared_bandwidth(resource_list, &ingress_keys)),
+    )
+}
+
+/// Parses the bandwidth declared in a resource list for each of the given keys
+pub(crate) fn declared_bandwidth(
+    resource_list: &serde_json::Value,
+    keys: &[&str],
+) -> Vec<Option<Result<Quantity, QuantityError>>> {
+    keys.iter()
+        .map(|key| {
+            resource_list
+                .get(key)
+                .and_then(|bandwidth| bandwidth.as_str())
+                .map(quantity::parse)
+        })
+        .collect()
+}
+
+/// Sums up the bandwidth declared using several keys, ignoring malformed values unless all of them are
+fn aggregate_bandwidth(
+    values: &[Option<Result<Quantity, QuantityError>>],
+) -> Option<Result<Quantity, QuantityError>> {
+    let declared = values.iter().flatten().collect::<Vec<_>>();
+    let valid = declared
+        .iter()
+        .filter_map(|value| value.as_ref().ok())
+        .collect::<Vec<_>>();
+
+    if valid.is_empty() {
+        return declared.first().map(|value| (*value).clone());
+    }
+
+    Some(valid.into_iter().sum())
+}
+
+/// Parses the extra resources of a `resources` object located at `path`, preferring limits over requests, and
+/// adding the patches stripping them in strip mode
+fn mutate_extra_resources(
+    resources: &serde_json::Value,
+    path: &str,
+    subject: &str,
+    props: &BandwidthProps,
+    patches: &mut Vec<PatchOperation>,
+    warnings: &mut Vec<String>,
+) -> [Option<Quantity>; 4] {
+    ExtraResource::ALL.map(|resource| {
+        let key = resource.resource_key(props);
+        let mut value = None;
+
+        for list in ["limits", "requests"] {
+            let Some(quantity) = resources
+                .get(list)
+                .and_then(|list| list.get(key))
+                .and_then(|quantity| quantity.as_str())
+            else {
+                continue;
+            };
+
+            match quantity::parse(quantity) {
+                Ok(quantity) if quantity <= Quantity::ZERO => warnings.push(format!(
+                    "{subject}: ignored non-positive {} {list}: {quantity}",
+                    resource.description()
+                )),
+                Ok(quantity) => {
+                    value = value.or(Some(quantity));
+
+                    if props.mode == BandwidthMode::Strip {
+                        patches.push(PatchOperation::Remove(RemoveOperation {
+                            path: format!("{path}/{list}/{}", escape_json_pointer(key)),
+                        }));
+                    }
+                }
+                Err(err) => warnings.push(format!(
+                    "{subject}: ignored malformed {} {list}: {err}",
+                    resource.description()
+                )),
+            }
+        }
+
+        value
+    })
+}
+
+/// Parses the per-network bandwidth of a `resources` object located at `path`, preferring limits over requests, and
+/// adding the patches stripping them in strip mode
+fn mutate_network_resources(
+    resources: &serde_json::Value,
+    path: &str,
+    subject: &str,
+    props: &BandwidthProps,
+    patches: &mut Vec<PatchOperation>,
+    warnings: &mut Vec<String>,
+) -> BTreeMap<(String, &'static str), Quantity> {
+    let mut values = BTreeMap::new();
+
+    for list in ["limits", "requests"] {
+        let Some(resource_list) = resources.get(list).and_then(|list| list.as_object()) else {
+            continue;
+        };
+
+        for (key, quantity) in resource_list {
+            let Some((network, direction)) =
+                network_resource(key, &props.network_bandwidth_resource_prefix)
+            else {
+                continue;
+            };
+            let Some(quantity) = quantity.as_str() else {
+                continue;
+            };
+
+            match quantity::parse(quantity) {
+                Ok(quantity) if quantity <= Quantity::ZERO => warnings.push(format!(
+                    "{subject}: ignored non-positive {direction} bandwidth {list} of network \"{network}\": {quantity}"
+                )),
+                Ok(quantity) => {
+                    values
+                        .entry((network.to_owned(), direction))
+                        .or_insert(quantity);
+
+                    if props.mode == BandwidthMode::Strip {
+                        patches.push(PatchOperation::Remove(RemoveOperation {
+                            path: format!("{path}/{list}/{}", escape_json_pointer(key)),
+                        }));
+                    }
+                }
+                Err(err) => warnings.push(format!(
+                    "{subject}: ignored malformed {direction} bandwidth {list} of network \"{network}\": {err}"
+                )),
+            }
+        }
+    }
+
+    values
+}
+
+/// Adds the bandwidth of the pod's secondary networks to the matching elements of its Multus networks annotation,
+/// returning the patch replacing the annotation, if any
+fn network_patch(
+    annotations: &BTreeMap<String, String>,
+    networks: BTreeMap<(String, &'static str), Option<Quantity>>,
+    metadata_pointer: &str,
+    warnings: &mut Vec<String>,
+) -> Option<PatchOperation> {
+    let mut bandwidths = BTreeMap::<_, (Option<Quantity>, Option<Quantity>)>::new();
+
+    for ((network, direction), value) in networks {
+        let bandwidth = bandwidths.entry(network).or_default();
+
+        match direction {
+            "egress" => bandwidth.0 = value,
+            _ => bandwidth.1 = value,
+        }
+    }
+
+    if bandwidths.is_empty() {
+        return None;
+    }
+
+    let mut elements = match annotations
+        .get(NETWORKS_ANNOTATION)
+        .map(|networks| parse_networks(networks))
+    {
+        Some(Ok(elements)) => elements,
+        Some(Err(err)) => {
+            warnings.push(format!(
+                "ignored malformed \"{NETWORKS_ANNOTATION}\" annotation, thus no network bandwidth will be shaped: {err}"
+            ));
+
+            return None;
+        }
+        None => Vec::new(),
+    };
+
+    for (network, (egress, ingress)) in bandwidths {
+        let mut attached = false;
+
+        for element in elements
+            .iter_mut()
+            .filter(|element| element.get("name").and_then(|name| name.as_str()) == Some(&network))
+        {
+            attached = true;
+            element.insert("bandwidth".to_owned(), bandwidth_entry(egress, ingress));
+        }
+
+        if !attached {
+            warnings.push(format!(
+                "network \"{network}\" is not attached to the pod, thus its bandwidth will not be shaped"
+            ));
+        }
+    }
+
+    // Only the annotation's elements are rewritten, thus pods without one stay untouched
+    if elements.is_empty() {
+        return None;
+    }
+
+    Some(PatchOperation::Add(AddOperation {
+        path: format!(
+            "{metadata_pointer}/annotations/{}",
+            escape_json_pointer(NETWORKS_ANNOTATION)
+        ),
+        value: serde_json::Value::String(serde_json::Value::from(elements).to_string()),
+    }))
+}
+
+/// Parses the bandwidth requests and limits of a `resources` object located at `path`,
+/// adding the patches required by the bandwidth mode
+fn mutate_resources(
+    resources: &serde_json::Value,
+    path: &str,
+    subject: &str,
+    props: &BandwidthProps,
+    patches: &mut Vec<PatchOperation>,
+    warnings: &mut Vec<String>,
+) -> (Option<Bandwidth>, Option<Bandwidth>) {
+    let [egress_keys, ingress_keys] = props.bandwidth_resource_keys();
+    let mut created = Vec::new();
+
+    let [(egress_request, egress_limit), (ingress_request, ingress_limit)] = [
+        ("egress", egress_keys),
+        ("ingress", ingress_keys),
+    ]
+    .map(|(direction, keys)| {
+        // -- Get the requests and limits of every key --
+        let declared = |list: &str| {
+            resources
+                .get(list)
+                .map(|resource_list| declared_bandwidth(resource_list, &keys))
+                .unwrap_or_else(|| vec![None; keys.len()])
+        };
+        let mut requests = declared("requests");
+        let mut limits = declared("limits");
+
+        // -- Warn about values which are ignored, or declared using deprecated keys --
+        for (list, values) in [("requests", &mut requests), ("limits", &mut limits)] {
+            for value in values.iter_mut() {
+                match value {
+                    Some(Err(err)) => warnings.push(format!(
+                        "{subject}: ignored malformed {direction} bandwidth {list}: {err}"
+                    )),
+                    // Non-positive values would otherwise vanish when aggregated, as containers count as zero
+                    Some(Ok(quantity)) if *quantity <= Quantity::ZERO => {
+                        warnings.push(format!(
+                            "{subject}: ignored non-positive {direction} bandwidth {list}: {quantity}"
+                        ));
+                        *value = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for (index, alias) in keys.iter().enumerate().skip(1) {
+            if requests[index].is_some() || limits[index].is_some() {
+                warnings.push(format!(
+                    "{subject}: resource key \"{alias}\" is deprecated, use \"{}\" instead",
+                    keys[0]
+                ));
+            }
+        }
+
+        let request = aggregate_bandwidth(&requests);
+        let limit = aggregate_bandwidth(&limits);
+
+        // The fill-missing mode takes care of values lacking their counterpart
+        match (&request, &limit) {
+            _ if matches!(props.mode, BandwidthMode::FillMissing) => {}
+            (None, Some(Ok(_))) => warnings.push(format!(
+                "{subject}: {direction} bandwidth limit has no request, thus schedulers will not account for it"
+            )),
+            (Some(Ok(_)), None) => warnings.push(format!(
+                "{subject}: {direction} bandwidth request has no limit, thus it will not be shaped"
+            )),
+            _ => {}
+        }
+
+        // -- Mutation modes --
+        for ((key, request), limit) in keys.iter().zip(&requests).zip(&limits) {
+            let key = escape_json_pointer(key);
+
+            match (props.mode, request, limit) {
+                // In annotate mode, no further operations have to be performed on the Kubernetes object
+                // thus it's a noop
+                (BandwidthMode::Annotate, _, _) => {}
+                // Strip custom bandwidth resource requests and limits if strip = true
+                (BandwidthMode::Strip, request, limit) => {
+                    for (list, value) in [("requests", request), ("limits", limit)] {
+                        if let Some(Ok(_)) = value {
+                            patches.push(PatchOperation::Remove(RemoveOperation {
+                                path: format!("{path}/{list}/{key}"),
+                            }));
+                        }
+                    }
+                }
+                // Overwrite limits of keys declaring both, requests and limits
+                (BandwidthMode::Overwrite | BandwidthMode::Ratio, Some(Ok(_)), Some(Ok(_))) => {
+                    patches.push(PatchOperation::Copy(CopyOperation {
+                        from: format!("{path}/requests/{key}"),
+                        path: format!("{path}/limits/{key}"),
+                    }));
+                }
+                (BandwidthMode::FillMissing, Some(Ok(_)), None)
+                | (BandwidthMode::FillMissing, None, Some(Ok(_))) => {
+                    let (from, to) = match request {
+                        Some(_) => ("requests", "limits"),
+                        None => ("limits", "requests"),
+                    };
+
+                    // Ensure the requests or limits exist before adding a key to them
+                    if !resources.get(to).is_some_and(|list| list.is_object())
+                        && !created.contains(&to)
+                    {
+                        patches.push(PatchOperation::Add(AddOperation {
+                            path: format!("{path}/{to}"),
+                            value: serde_json::json!({}),
+                        }));
+                        created.push(to);
+                    }
+
+                    patches.push(PatchOperation::Copy(CopyOperation {
+                        from: format!("{path}/{from}/{key}"),
+                        path: format!("{path}/{to}/{key}"),
+                    }));
+                }
+                _ => {}
+            }
+        }
+
+        match props.mode {
+            // Filled keys count towards both, requests and limits
+            BandwidthMode::FillMissing => {
+                let fill = |values: &[Option<Result<Quantity, QuantityError>>],
+                            counterparts: &[Option<Result<Quantity, QuantityError>>]| {
+                    let filled = values
+                        .iter()
+                        .zip(counterparts)
+                        .map(|(value, counterpart)| match (value, counterpart) {
+                            (None, Some(Ok(counterpart))) => Some(Ok(*counterpart)),
+                            _ => value.clone(),
+                        })
+                        .collect::<Vec<_>>();
+
+                    aggregate_bandwidth(&filled)
+                };
+
+                (fill(&requests, &limits), fill(&limits, &requests))
+            }
+            // Extended resources require limits to equal requests, thus the scaled limits only end up in the annotations
+            BandwidthMode::Ratio => {
+                let limit = match &request {
+                    Some(Ok(request)) => Some(*request * props.overcommit_ratio),
+                    _ => limit,
+                };
+
+                (request, limit)
+            }
+            _ => (request, limit),
+        }
+    });
+
+    let requests = (egress_request, ingress_request);
+    let limits = (egress_limit, ingress_limit);
+
+    match props.mode {
+        // The fill-missing mode creates the requests and limits if necessary
+        BandwidthMode::FillMissing => return (Some(requests), Some(limits)),
+        // Limits are derived from requests in ratio mode
+        BandwidthMode::Ratio => return (resources.get("requests").map(|_| requests), Some(limits)),
+        _ => {}
+    }
+
+    (
+        resources.get("requests").map(|_| requests),
+        resources.get("limits").map(|_| limits),
+    )
+}
+
+/// Reads a setting from a label of the object's namespace, falling back to the given default
+fn namespace_setting<T: ValueEnum>(
+    namespaces: &NamespaceCache,
+    obj: &DynamicObject,
+    label: &str,
+    default: T,
+) -> Result<T> {
+    match namespace_label(namespaces, obj, label)? {
+        Some(value) => T::from_str(&value, false)
+            .map_err(|err| eyre!("Invalid \"{label}\" label on namespace: {err}")),
+        None => Ok(default),
+    }
+}
+
+/// Looks up a label of the object's pod template, falling back to the label of its namespace
+fn template_or_namespace_label(
+    namespaces: &NamespaceCache,
+    obj: &DynamicObject,
+    locators: &[PodTemplateLocator],
+    key: &str,
+) -> Result<Option<String>> {
+    match template_label(obj, locators, key) {
+        Some(value) => Ok(Some(value)),
+        None => namespace_label(namespaces, obj, key),
+    }
+}
+
+/// Looks up a label of the object's pod template, falling back to a workload's own labels
+fn template_label(
+    obj: &DynamicObject,
+    locators: &[PodTemplateLocator],
+    key: &str,
+) -> Option<String> {
+    PodTemplate::locate(obj, locators)
+        .and_then(|template| template.labels().remove(key))
+        .or_else(|| obj.labels().get(key).cloned())
+}
+
+/// Looks up a label of the object's namespace, returning `None` if either is unknown to the namespace cache
+fn namespace_label(
+    namespaces: &NamespaceCache,
+    obj: &DynamicObject,
+    key: &str,
+) -> Result<Option<String>> {
+    let Some(obj_ns) = obj.namespace() else {
+        return Ok(None);
+    };
+
+    Ok(namespaces
+        .get(&obj_ns)
+        .and_then(|namespace| namespace.labels)
+        .and_then(|mut labels| labels.remove(key)))
+}
+
+/// Pod and namespace label overriding the pod's scheduler
+const SCHEDULER_LABEL: &str = "nbam-default-scheduler";
+
+fn mutate_scheduler(
+    res: AdmissionResponse,
     obj: &DynamicObject,
     namespaces: NamespaceCache,
+    locators: &[PodTemplateLocator],
 ) -> Result<AdmissionResponse> {
-    let mut patches = Vec::new();
+    let key = SCHEDULER_LABEL;
 
-    let key = "nbam-default-scheduler";
-
-    // Check if the pod has a scheduler label
-    let scheduler_name = if let Some(default_scheduler) = obj.labels().get(key) {
-        default_scheduler.to_owned()
+    // Check if the pod (template) has a scheduler label
+    let scheduler_name = if let Some(default_scheduler) = template_label(obj, locators, key) {
+        default_scheduler
     } else {
         let obj_ns = obj.namespace().context(format!(
             "Could not determine namespace for object: {}",
@@ -332,10 +1755,6 @@ fn mutate_scheduler(
         ))?;
 
         // Otherwise try obtaining the scheduler name from the namespace cache
-        let namespaces = namespaces
-            .lock()
-            .map_err(|err| color_eyre::eyre::eyre!("Could not acquire namespace cache: {err}"))?;
-
         let namespace = namespaces.get(&obj_ns).context(format!(
             "Failed to get namespace \"{obj_ns}\" from namespace cache"
         ))?;
@@ -351,16 +1770,1315 @@ fn mutate_scheduler(
             .to_owned()
     };
 
-    patches.push(PatchOperation::Add(AddOperation {
-        path: "/spec/schedulerName".into(),
+    let (patches, warnings) = scheduler_patches(obj, scheduler_name, locators);
+
+    respond(res, patches, warnings)
+}
+
+/// Computes the patches and warnings overriding the pod's scheduler
+fn scheduler_patches(
+    obj: &DynamicObject,
+    scheduler_name: String,
+    locators: &[PodTemplateLocator],
+) -> (Vec<PatchOperation>, Vec<String>) {
+    // Objects without a pod template are left untouched
+    let Some(template) = PodTemplate::locate(obj, locators) else {
+        return (Vec::new(), Vec::new());
+    };
+
+    let mut warnings = Vec::new();
+
+    // Pods explicitly picking a scheduler other than the default one get overridden as well
+    if let Some(existing) = template
+        .spec()
+        .and_then(|spec| spec.get("schedulerName"))
+        .and_then(|scheduler_name| scheduler_name.as_str())
+    {
+        if existing != "default-scheduler" && existing != scheduler_name {
+            warnings.push(format!(
+                "replaced scheduler {existing:?} with {scheduler_name:?}"
+            ));
+        }
+    }
+
+    let patches = vec![PatchOperation::Add(AddOperation {
+        path: format!("{}/schedulerName", template.spec_pointer()),
         value: serde_json::Value::String(scheduler_name),
-    }));
+    })];
 
-    Ok(if !patches.is_empty() {
-        res.with_patch(json_patch::Patch(patches))?
-    } else {
-        res
-    })
+    (patches, warnings)
 }
 
-// TODO: Add tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(value: serde_json::Value) -> DynamicObject {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn namespace(labels: &[(&str, &str)]) -> NamespaceCache {
+        NamespaceCache::with_namespaces(
+            vec![kube::core::ObjectMeta {
+                name: Some("nbam-test".to_owned()),
+                labels: Some(
+                    labels
+                        .iter()
+                        .map(|(key, value)| ((*key).to_owned(), (*value).to_owned()))
+                        .collect(),
+                ),
+                ..Default::default()
+            }],
+            true,
+        )
+    }
+
+    fn pod(resources: serde_json::Value, annotations: serde_json::Value) -> DynamicObject {
+        object(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": { "name": "pod", "namespace": "nbam-test", "annotations": annotations },
+            "spec": { "containers": [{ "name": "app", "resources": resources }] },
+        }))
+    }
+
+    fn init_pod(resources: serde_json::Value) -> DynamicObject {
+        object(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": { "name": "pod", "namespace": "nbam-test" },
+            "spec": {
+                "initContainers": [{ "name": "init", "resources": resources }],
+                "containers": [{ "name": "app" }],
+            },
+        }))
+    }
+
+    /// Applies the patches of the props' bandwidth mode to the object, returning the result and the warnings
+    fn patched(obj: &DynamicObject, props: &BandwidthProps) -> (serde_json::Value, Vec<String>) {
+        updated(obj, None, props)
+    }
+
+    /// Applies the patches of the props' bandwidth mode to an update of the old object
+    fn updated(
+        obj: &DynamicObject,
+        old: Option<&DynamicObject>,
+        props: &BandwidthProps,
+    ) -> (serde_json::Value, Vec<String>) {
+        let (patches, warnings) = bandwidth_patches(obj, old, props).unwrap();
+        let mut value = serde_json::to_value(obj).unwrap();
+        json_patch::patch(&mut value, &json_patch::Patch(patches)).unwrap();
+
+        (value, warnings)
+    }
+
+    fn quantity(value: &str) -> Quantity {
+        quantity::parse(value).unwrap()
+    }
+
+    fn deployment(resources: serde_json::Value) -> DynamicObject {
+        object(serde_json::json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": { "name": "deployment", "namespace": "nbam-test" },
+            "spec": {
+                "template": {
+                    "spec": { "containers": [{ "name": "app", "resources": resources }] },
+                },
+            },
+        }))
+    }
+
+    fn scheduled_pod(labels: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": { "name": "pod", "namespace": "nbam-test", "labels": labels },
+            "spec": {
+                "containers": [{
+                    "name": "app",
+                    "resources": {
+                        "requests": { "networking.k8s.io/egress-bandwidth": "1M" },
+                        "limits": { "networking.k8s.io/egress-bandwidth": "1M" },
+                    },
+                }],
+            },
+        })
+    }
+
+    /// Builds an admission review of a request creating the object in the "nbam-test" namespace
+    fn review(obj: serde_json::Value) -> AdmissionReview<DynamicObject> {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": {
+                "uid": "705ab4f5-6393-11e8-b7cc-42010a800002",
+                "kind": { "group": "", "version": "v1", "kind": "Pod" },
+                "resource": { "group": "", "version": "v1", "resource": "pods" },
+                "namespace": "nbam-test",
+                "operation": "CREATE",
+                "userInfo": {},
+                "object": obj,
+            },
+        }))
+        .unwrap()
+    }
+
+    /// Applies the patch the mutation adds to the response to a request creating the object
+    fn mutated(
+        obj: &DynamicObject,
+        mutation: impl FnOnce(AdmissionResponse) -> Result<AdmissionResponse>,
+    ) -> serde_json::Value {
+        let mut value = serde_json::to_value(obj).unwrap();
+        let req: AdmissionRequest<DynamicObject> = review(value.clone()).try_into().unwrap();
+        let res = mutation(AdmissionResponse::from(&req)).unwrap();
+
+        json_patch::patch(
+            &mut value,
+            &serde_json::from_slice::<json_patch::Patch>(&res.patch.unwrap()).unwrap(),
+        )
+        .unwrap();
+
+        value
+    }
+
+    /// Runs the handler on an admission request creating the object in the "nbam-test" namespace, whose lookup fails
+    async fn admit(
+        obj: serde_json::Value,
+        mode: Mode,
+        policy: NamespaceLookupPolicy,
+    ) -> serde_json::Value {
+        use axum::body::HttpBody;
+
+        // Nothing listens on the port, thus fetching the namespace fails right away
+        let client =
+            kube::Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap()))
+                .unwrap();
+        let lookup = NamespaceLookup::with_client(
+            NamespaceCache::with_namespaces(Vec::new(), true),
+            client,
+            std::time::Duration::from_secs(1),
+            policy,
+        );
+
+        let mut body = handler(Json(review(obj)), mode, lookup)
+            .await
+            .into_response()
+            .into_body();
+        let review: serde_json::Value =
+            serde_json::from_slice(&body.data().await.unwrap().unwrap()).unwrap();
+
+        review["response"].clone()
+    }
+
+    #[test]
+    fn test_resolve_conflict_without_existing() {
+        let mut warnings = Vec::new();
+
+        for policy in ConflictPolicy::value_variants() {
+            assert_eq!(
+                resolve_conflict(
+                    *policy,
+                    OutputFormat::Canonical,
+                    "kubernetes.io/egress-bandwidth",
+                    None,
+                    quantity("2M"),
+                    &mut warnings,
+                )
+                .unwrap(),
+                Some(quantity("2M"))
+            );
+        }
+
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_conflict_respect_existing() {
+        let mut warnings = Vec::new();
+
+        assert_eq!(
+            resolve_conflict(
+                ConflictPolicy::RespectExisting,
+                OutputFormat::Canonical,
+                "kubernetes.io/egress-bandwidth",
+                Some(&"1M".to_owned()),
+                quantity("2M"),
+                &mut warnings,
+            )
+            .unwrap(),
+            None
+        );
+        assert_eq!(
+            warnings,
+            ["kept existing kubernetes.io/egress-bandwidth annotation \"1M\" instead of the computed value \"2M\""]
+        );
+    }
+
+    #[test]
+    fn test_resolve_conflict_overwrite() {
+        let mut warnings = Vec::new();
+
+        assert_eq!(
+            resolve_conflict(
+                ConflictPolicy::Overwrite,
+                OutputFormat::Canonical,
+                "kubernetes.io/egress-bandwidth",
+                Some(&"1M".to_owned()),
+                quantity("2M"),
+                &mut warnings,
+            )
+            .unwrap(),
+            Some(quantity("2M"))
+        );
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_resolve_conflict_take_min() {
+        let mut warnings = Vec::new();
+
+        let resolve = |existing: &str, warnings: &mut Vec<String>| {
+            resolve_conflict(
+                ConflictPolicy::TakeMin,
+                OutputFormat::Canonical,
+                "kubernetes.io/egress-bandwidth",
+                Some(&existing.to_owned()),
+                quantity("2M"),
+                warnings,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(resolve("1M", &mut warnings), None);
+        assert_eq!(resolve("3M", &mut warnings), Some(quantity("2M")));
+        assert_eq!(warnings.len(), 2);
+    }
+
+    #[test]
+    fn test_resolve_conflict_take_min_with_invalid_existing() {
+        let mut warnings = Vec::new();
+
+        assert_eq!(
+            resolve_conflict(
+                ConflictPolicy::TakeMin,
+                OutputFormat::Canonical,
+                "kubernetes.io/egress-bandwidth",
+                Some(&"fast".to_owned()),
+                quantity("2M"),
+                &mut warnings,
+            )
+            .unwrap(),
+            Some(quantity("2M"))
+        );
+        assert_eq!(warnings.len(), 1);
+        assert!(
+            warnings[0].contains("as the existing one is invalid"),
+            "{warnings:?}"
+        );
+    }
+
+    #[test]
+    fn test_resolve_conflict_deny() {
+        let err = resolve_conflict(
+            ConflictPolicy::Deny,
+            OutputFormat::Canonical,
+            "kubernetes.io/egress-bandwidth",
+            Some(&"1M".to_owned()),
+            quantity("2M"),
+            &mut Vec::new(),
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Annotation \"kubernetes.io/egress-bandwidth\" is already set to \"1M\", conflicting with the computed value \"2M\""
+        );
+    }
+
+    #[test]
+    fn test_resolve_conflict_mbps_round_trip() {
+        let mut warnings = Vec::new();
+
+        // 1.5M is written as 2 Mbit/s, thus reading it back differs from the computed value, while still matching it
+        for policy in ConflictPolicy::value_variants() {
+            assert_eq!(
+                resolve_conflict(
+                    *policy,
+                    OutputFormat::Mbps,
+                    "ovn.kubernetes.io/egress_rate",
+                    Some(&"2".to_owned()),
+                    quantity("1500k"),
+                    &mut warnings,
+                )
+                .unwrap(),
+                Some(quantity("1500k"))
+            );
+        }
+
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_conflict_policy_namespace_override() {
+        let props = BandwidthProps::with_defaults(
+            BandwidthMode::Annotate,
+            namespace(&[(CONFLICT_POLICY_LABEL, "respect-existing")]),
+        );
+        let obj = pod(
+            serde_json::json!({
+                "requests": { "networking.k8s.io/egress-bandwidth": "2M" },
+                "limits": { "networking.k8s.io/egress-bandwidth": "2M" },
+            }),
+            serde_json::json!({ "kubernetes.io/egress-bandwidth": "1M" }),
+        );
+
+        let (patched, warnings) = patched(&obj, &props);
+
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/egress-bandwidth"],
+            "1M"
+        );
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_strict_mode_denies_invalid_bandwidth() {
+        let props = BandwidthProps {
+            strict: true,
+            ..BandwidthProps::with_defaults(BandwidthMode::Annotate, namespace(&[]))
+        };
+        let obj = pod(
+            serde_json::json!({ "limits": { "networking.k8s.io/egress-bandwidth": "1Kb" } }),
+            serde_json::json!({}),
+        );
+
+        assert!(bandwidth_patches(&obj, None, &props)
+            .unwrap_err()
+            .to_string()
+            .starts_with("Invalid bandwidth resources: container \"app\""));
+
+        // Without strict mode, the malformed value is merely ignored
+        let props = BandwidthProps {
+            strict: false,
+            ..props
+        };
+
+        assert!(bandwidth_patches(&obj, None, &props).is_ok());
+    }
+
+    #[test]
+    fn test_clamp_rewrites_before_overwriting() {
+        let props = BandwidthProps::with_defaults(
+            BandwidthMode::Overwrite,
+            namespace(&[(MAX_EGRESS_LABEL, "60M")]),
+        );
+        let obj = pod(
+            serde_json::json!({
+                "requests": { "networking.k8s.io/egress-bandwidth": "50M" },
+                "limits": { "networking.k8s.io/egress-bandwidth": "80M" },
+            }),
+            serde_json::json!({}),
+        );
+
+        let (patched, _) = patched(&obj, &props);
+
+        // The clamped limit is replaced first, thus the copied request wins
+        assert_eq!(
+            patched["spec"]["containers"][0]["resources"]["limits"],
+            serde_json::json!({ "networking.k8s.io/egress-bandwidth": "50M" })
+        );
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/egress-bandwidth"],
+            "60M"
+        );
+        assert_eq!(
+            patched["metadata"]["annotations"][CLAMPED_ANNOTATION],
+            "container app: egress limit 80M"
+        );
+    }
+
+    #[test]
+    fn test_clamp_rewrites_before_stripping() {
+        let props = BandwidthProps::with_defaults(
+            BandwidthMode::Strip,
+            namespace(&[(MAX_EGRESS_LABEL, "10M")]),
+        );
+        let obj = pod(
+            serde_json::json!({
+                "requests": { "networking.k8s.io/egress-bandwidth": "50M" },
+                "limits": { "networking.k8s.io/egress-bandwidth": "50M" },
+            }),
+            serde_json::json!({}),
+        );
+
+        let (patched, _) = patched(&obj, &props);
+
+        assert_eq!(
+            patched["spec"]["containers"][0]["resources"],
+            serde_json::json!({ "requests": {}, "limits": {} })
+        );
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/egress-request"],
+            "10M"
+        );
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/egress-bandwidth"],
+            "10M"
+        );
+    }
+
+    #[test]
+    fn test_clamp_keeps_summed_aliases() {
+        let props = BandwidthProps {
+            egress_bandwidth_resource_key_aliases: vec!["example.com/egress-bandwidth".to_owned()],
+            ..BandwidthProps::with_defaults(
+                BandwidthMode::Annotate,
+                namespace(&[(MAX_EGRESS_LABEL, "50M")]),
+            )
+        };
+        let resources = serde_json::json!({
+            "requests": { "networking.k8s.io/egress-bandwidth": "10M" },
+            "limits": {
+                "networking.k8s.io/egress-bandwidth": "30M",
+                "example.com/egress-bandwidth": "30M",
+            },
+        });
+        let obj = pod(resources.clone(), serde_json::json!({}));
+
+        let (patched, _) = patched(&obj, &props);
+
+        // The summed up limit isn't declared by any single key, thus only the annotation is clamped
+        assert_eq!(patched["spec"]["containers"][0]["resources"], resources);
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/egress-bandwidth"],
+            "50M"
+        );
+        assert_eq!(
+            patched["metadata"]["annotations"][CLAMPED_ANNOTATION],
+            "container app: egress limit 60M"
+        );
+    }
+
+    #[test]
+    fn test_clamp_keeps_derived_limits() {
+        let props = BandwidthProps::with_defaults(
+            BandwidthMode::Ratio,
+            namespace(&[(MAX_EGRESS_LABEL, "50M")]),
+        );
+        let obj = pod(
+            serde_json::json!({
+                "requests": { "networking.k8s.io/egress-bandwidth": "40M" },
+                "limits": { "networking.k8s.io/egress-bandwidth": "40M" },
+            }),
+            serde_json::json!({}),
+        );
+
+        let (patched, _) = patched(&obj, &props);
+
+        // The derived limit of 80M only exists in the annotation, thus the declared one stays untouched
+        assert_eq!(
+            patched["spec"]["containers"][0]["resources"]["limits"],
+            serde_json::json!({ "networking.k8s.io/egress-bandwidth": "40M" })
+        );
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/egress-bandwidth"],
+            "50M"
+        );
+    }
+
+    #[test]
+    fn test_clamp_deny_enforcement() {
+        let props = BandwidthProps::with_defaults(
+            BandwidthMode::Annotate,
+            namespace(&[(MAX_EGRESS_LABEL, "60M"), (CLAMP_ENFORCEMENT_LABEL, "deny")]),
+        );
+        let obj = pod(
+            serde_json::json!({
+                "requests": { "networking.k8s.io/egress-bandwidth": "50M" },
+                "limits": { "networking.k8s.io/egress-bandwidth": "80M" },
+            }),
+            serde_json::json!({}),
+        );
+
+        assert_eq!(
+            bandwidth_patches(&obj, None, &props)
+                .unwrap_err()
+                .to_string(),
+            "The egress limit of container \"app\" (80M) exceeds the namespace's maximum of 60M"
+        );
+    }
+
+    #[test]
+    fn test_fill_missing_limits_object() {
+        let props = BandwidthProps::with_defaults(BandwidthMode::FillMissing, namespace(&[]));
+        let obj = pod(
+            serde_json::json!({ "requests": { "networking.k8s.io/egress-bandwidth": "1M" } }),
+            serde_json::json!({}),
+        );
+
+        let (patched, warnings) = patched(&obj, &props);
+
+        assert_eq!(
+            patched["spec"]["containers"][0]["resources"]["limits"],
+            serde_json::json!({ "networking.k8s.io/egress-bandwidth": "1M" })
+        );
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/egress-bandwidth"],
+            "1M"
+        );
+        assert!(warnings.is_empty(), "{warnings:?}");
+    }
+
+    #[test]
+    fn test_fill_missing_requests_object() {
+        let props = BandwidthProps::with_defaults(BandwidthMode::FillMissing, namespace(&[]));
+        let obj = pod(
+            serde_json::json!({
+                "limits": {
+                    "networking.k8s.io/egress-bandwidth": "1M",
+                    "networking.k8s.io/ingress-bandwidth": "2M",
+                },
+            }),
+            serde_json::json!({}),
+        );
+
+        let (patched, _) = patched(&obj, &props);
+
+        // Both keys are copied into a single requests object
+        assert_eq!(
+            patched["spec"]["containers"][0]["resources"]["requests"],
+            serde_json::json!({
+                "networking.k8s.io/egress-bandwidth": "1M",
+                "networking.k8s.io/ingress-bandwidth": "2M",
+            })
+        );
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/ingress-request"],
+            "2M"
+        );
+    }
+
+    #[test]
+    fn test_fill_missing_keys_of_both_lists() {
+        let props = BandwidthProps::with_defaults(BandwidthMode::FillMissing, namespace(&[]));
+        let obj = pod(
+            serde_json::json!({
+                "requests": { "networking.k8s.io/egress-bandwidth": "1M" },
+                "limits": { "networking.k8s.io/ingress-bandwidth": "2M" },
+            }),
+            serde_json::json!({}),
+        );
+
+        let (patched, _) = patched(&obj, &props);
+        let both = serde_json::json!({
+            "networking.k8s.io/egress-bandwidth": "1M",
+            "networking.k8s.io/ingress-bandwidth": "2M",
+        });
+
+        assert_eq!(
+            patched["spec"]["containers"][0]["resources"],
+            serde_json::json!({ "requests": both, "limits": both })
+        );
+
+        for (annotation, value) in [
+            ("kubernetes.io/egress-request", "1M"),
+            ("kubernetes.io/egress-bandwidth", "1M"),
+            ("kubernetes.io/ingress-request", "2M"),
+            ("kubernetes.io/ingress-bandwidth", "2M"),
+        ] {
+            assert_eq!(patched["metadata"]["annotations"][annotation], value);
+        }
+    }
+
+    #[test]
+    fn test_mutate_reads_template_labels() {
+        let props = BandwidthProps::with_defaults(BandwidthMode::Annotate, namespace(&[]));
+        let obj = object(serde_json::json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": { "name": "deployment", "namespace": "nbam-test" },
+            "spec": {
+                "template": {
+                    "metadata": {
+                        "labels": { MODE_LABEL: "overwrite", SCHEDULER_LABEL: "my-scheduler" },
+                    },
+                    "spec": {
+                        "containers": [{
+                            "name": "app",
+                            "resources": {
+                                "requests": { "networking.k8s.io/egress-bandwidth": "1M" },
+                                "limits": { "networking.k8s.io/egress-bandwidth": "2M" },
+                            },
+                        }],
+                    },
+                },
+            },
+        }));
+
+        let patched = mutated(&obj, |res| mutate(res, &obj, None, &props));
+        let template = &patched["spec"]["template"];
+
+        assert_eq!(
+            template["spec"]["containers"][0]["resources"]["limits"],
+            serde_json::json!({ "networking.k8s.io/egress-bandwidth": "1M" })
+        );
+        assert_eq!(template["spec"]["schedulerName"], "my-scheduler");
+        assert_eq!(
+            template["metadata"]["annotations"]["kubernetes.io/egress-bandwidth"],
+            "2M"
+        );
+    }
+
+    #[test]
+    fn test_mutate_scheduler_reads_template_labels() {
+        let obj = object(serde_json::json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": { "name": "job", "namespace": "nbam-test" },
+            "spec": {
+                "template": {
+                    "metadata": { "labels": { SCHEDULER_LABEL: "my-scheduler" } },
+                    "spec": { "containers": [] },
+                },
+            },
+        }));
+
+        // The namespace doesn't carry the label, thus looking it up would fail
+        let patched = mutated(&obj, |res| mutate_scheduler(res, &obj, namespace(&[]), &[]));
+
+        assert_eq!(
+            patched["spec"]["template"]["spec"]["schedulerName"],
+            "my-scheduler"
+        );
+    }
+
+    #[test]
+    fn test_overwrite_mutated_template_again() {
+        let props = BandwidthProps::with_defaults(BandwidthMode::Overwrite, namespace(&[]));
+        let obj = deployment(serde_json::json!({
+            "requests": { "networking.k8s.io/egress-bandwidth": "1M" },
+            "limits": { "networking.k8s.io/egress-bandwidth": "2M" },
+        }));
+
+        // Scaling the Deployment leaves its template alone
+        let (created, _) = patched(&obj, &props);
+        let created = object(created);
+        let (updated, warnings) = updated(&created, Some(&created), &props);
+        let created = serde_json::to_value(&created).unwrap();
+
+        assert_eq!(updated, created);
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(
+            updated["spec"]["template"]["metadata"]["annotations"]
+                ["kubernetes.io/egress-bandwidth"],
+            "2M"
+        );
+    }
+
+    #[test]
+    fn test_strip_mutated_template_again() {
+        let props = BandwidthProps::with_defaults(
+            BandwidthMode::Strip,
+            namespace(&[(DEFAULT_EGRESS_LIMIT_LABEL, "10M")]),
+        );
+        let obj = deployment(serde_json::json!({
+            "requests": { "networking.k8s.io/egress-bandwidth": "50M" },
+            "limits": { "networking.k8s.io/egress-bandwidth": "50M" },
+        }));
+
+        let (created, _) = patched(&obj, &props);
+        let created = object(created);
+        let (updated, _) = updated(&created, Some(&created), &props);
+        let created = serde_json::to_value(&created).unwrap();
+
+        // The stripped containers don't fall back to the namespace's defaults
+        assert_eq!(updated, created);
+        assert_eq!(
+            updated["spec"]["template"]["metadata"]["annotations"]
+                ["kubernetes.io/egress-bandwidth"],
+            "50M"
+        );
+    }
+
+    #[test]
+    fn test_extra_resource_annotations() {
+        let resources = serde_json::json!({
+            "limits": {
+                "networking.k8s.io/egress-burst": "10M",
+                "networking.k8s.io/ingress-packet-rate": "1k",
+            },
+        });
+        let obj = pod(resources, serde_json::json!({}));
+
+        let props = BandwidthProps::with_defaults(
+            BandwidthMode::Annotate,
+            namespace(&[(CNI_PROFILE_LABEL, "calico")]),
+        );
+        let (calico, warnings) = patched(&obj, &props);
+
+        assert_eq!(
+            calico["metadata"]["annotations"],
+            serde_json::json!({
+                "nba-admission": "true",
+                "qos.projectcalico.org/egressBurst": "10M",
+                "qos.projectcalico.org/ingressPacketRate": "1k",
+            })
+        );
+        assert!(warnings.is_empty(), "{warnings:?}");
+
+        // The bandwidth plugin supports neither of them
+        let props = BandwidthProps::with_defaults(BandwidthMode::Annotate, namespace(&[]));
+        let (bandwidth_plugin, warnings) = patched(&obj, &props);
+
+        assert_eq!(
+            bandwidth_plugin["metadata"]["annotations"],
+            serde_json::json!({ "nba-admission": "true" })
+        );
+        assert_eq!(
+            warnings,
+            [
+                "the bandwidth-plugin CNI profile does not support the egress burst, thus it will not be applied",
+                "the bandwidth-plugin CNI profile does not support the ingress packet rate, thus it will not be applied",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aggregate_bandwidth() {
+        let malformed = quantity::parse("1Kb");
+
+        assert_eq!(aggregate_bandwidth(&[None, None]), None);
+        assert_eq!(
+            aggregate_bandwidth(&[Some(Ok(quantity("1M"))), None, Some(Ok(quantity("500k")))]),
+            Some(Ok(quantity("1500k")))
+        );
+
+        // Malformed values are ignored as long as any other value is valid
+        assert_eq!(
+            aggregate_bandwidth(&[Some(malformed.clone()), Some(Ok(quantity("1M")))]),
+            Some(Ok(quantity("1M")))
+        );
+        assert_eq!(
+            aggregate_bandwidth(&[None, Some(malformed.clone()), Some(quantity::parse("x"))]),
+            Some(malformed)
+        );
+    }
+
+    #[test]
+    fn test_deprecated_resource_key_aliases() {
+        let props = BandwidthProps {
+            egress_bandwidth_resource_key_aliases: vec!["example.com/egress-bandwidth".to_owned()],
+            ..BandwidthProps::with_defaults(BandwidthMode::Annotate, namespace(&[]))
+        };
+        let obj = pod(
+            serde_json::json!({
+                "requests": { "example.com/egress-bandwidth": "1M" },
+                "limits": {
+                    "networking.k8s.io/egress-bandwidth": "1M",
+                    "example.com/egress-bandwidth": "1M",
+                },
+            }),
+            serde_json::json!({}),
+        );
+
+        let (patched, warnings) = patched(&obj, &props);
+
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/egress-request"],
+            "1M"
+        );
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/egress-bandwidth"],
+            "2M"
+        );
+        assert_eq!(
+            warnings,
+            ["container \"app\": resource key \"example.com/egress-bandwidth\" is deprecated, use \"networking.k8s.io/egress-bandwidth\" instead"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_lookup_of_required_namespace() {
+        let mode = || {
+            Mode::Scheduler(
+                NamespaceCache::with_namespaces(Vec::new(), true),
+                Vec::new(),
+            )
+        };
+
+        let res = admit(
+            scheduled_pod(serde_json::json!({})),
+            mode(),
+            NamespaceLookupPolicy::Deny,
+        )
+        .await;
+
+        assert_eq!(res["allowed"], false);
+        assert!(res["status"]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Failed to fetch namespace \"nbam-test\": "));
+
+        let res = admit(
+            scheduled_pod(serde_json::json!({})),
+            mode(),
+            NamespaceLookupPolicy::Allow,
+        )
+        .await;
+
+        assert_eq!(res["allowed"], true);
+        assert!(res.get("patch").is_none());
+        assert!(res["warnings"][0]
+            .as_str()
+            .unwrap()
+            .ends_with(", thus the object was admitted without mutation"));
+    }
+
+    #[tokio::test]
+    async fn test_namespace_not_required_by_scheduler_label() {
+        let res = admit(
+            scheduled_pod(serde_json::json!({ SCHEDULER_LABEL: "my-scheduler" })),
+            Mode::Scheduler(
+                NamespaceCache::with_namespaces(Vec::new(), true),
+                Vec::new(),
+            ),
+            NamespaceLookupPolicy::Deny,
+        )
+        .await;
+
+        assert_eq!(res["allowed"], true);
+        assert!(res.get("patch").is_some());
+        assert!(res.get("warnings").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failed_lookup_of_optional_namespace() {
+        let props = BandwidthProps::with_defaults(
+            BandwidthMode::Annotate,
+            NamespaceCache::with_namespaces(Vec::new(), true),
+        );
+
+        let res = admit(
+            scheduled_pod(serde_json::json!({})),
+            Mode::Bandwidth(props),
+            NamespaceLookupPolicy::Deny,
+        )
+        .await;
+
+        // The deployment-wide settings apply instead of the namespace's ones
+        assert_eq!(res["allowed"], true);
+        assert!(res.get("patch").is_some());
+        assert!(res["warnings"][0]
+            .as_str()
+            .unwrap()
+            .ends_with(", thus the deployment-wide settings were applied"));
+    }
+
+    #[test]
+    fn test_mutate_reads_workload_labels() {
+        let props = BandwidthProps::with_defaults(BandwidthMode::Annotate, namespace(&[]));
+        let mut obj = deployment(serde_json::json!({
+            "limits": { "networking.k8s.io/egress-bandwidth": "2M" },
+        }));
+        obj.metadata.labels = Some(
+            [
+                (MODE_LABEL.to_owned(), "annotate".to_owned()),
+                (SCHEDULER_LABEL.to_owned(), "my-scheduler".to_owned()),
+            ]
+            .into(),
+        );
+
+        let patched = mutated(&obj, |res| mutate(res, &obj, None, &props));
+        let template = &patched["spec"]["template"];
+
+        assert_eq!(template["spec"]["schedulerName"], "my-scheduler");
+        assert_eq!(
+            template["metadata"]["annotations"]["kubernetes.io/egress-bandwidth"],
+            "2M"
+        );
+    }
+
+    #[test]
+    fn test_ignore_non_positive_bandwidth() {
+        let props = BandwidthProps::with_defaults(BandwidthMode::Annotate, namespace(&[]));
+        let obj = pod(
+            serde_json::json!({
+                "requests": { "networking.k8s.io/egress-bandwidth": "-1M" },
+                "limits": { "networking.k8s.io/egress-bandwidth": "0" },
+            }),
+            serde_json::json!({}),
+        );
+
+        let (patched, warnings) = patched(&obj, &props);
+
+        assert_eq!(
+            patched["metadata"]["annotations"],
+            serde_json::json!({ "nba-admission": "true" })
+        );
+        assert_eq!(
+            warnings,
+            [
+                "container \"app\": ignored non-positive egress bandwidth requests: -1M",
+                "container \"app\": ignored non-positive egress bandwidth limits: 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strip_init_containers() {
+        let props = BandwidthProps::with_defaults(BandwidthMode::Strip, namespace(&[]));
+        let obj = init_pod(serde_json::json!({
+            "requests": { "networking.k8s.io/egress-bandwidth": "1M" },
+            "limits": { "networking.k8s.io/egress-bandwidth": "2M" },
+        }));
+
+        let (patched, _) = patched(&obj, &props);
+
+        assert_eq!(
+            patched["spec"]["initContainers"][0]["resources"],
+            serde_json::json!({ "requests": {}, "limits": {} })
+        );
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/egress-bandwidth"],
+            "2M"
+        );
+    }
+
+    #[test]
+    fn test_overwrite_init_containers() {
+        let props = BandwidthProps::with_defaults(BandwidthMode::Overwrite, namespace(&[]));
+        let obj = init_pod(serde_json::json!({
+            "requests": { "networking.k8s.io/egress-bandwidth": "1M" },
+            "limits": { "networking.k8s.io/egress-bandwidth": "2M" },
+        }));
+
+        let (patched, _) = patched(&obj, &props);
+
+        assert_eq!(
+            patched["spec"]["initContainers"][0]["resources"]["limits"],
+            serde_json::json!({ "networking.k8s.io/egress-bandwidth": "1M" })
+        );
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/egress-request"],
+            "1M"
+        );
+    }
+
+    #[test]
+    fn test_pod_resources_take_precedence() {
+        let props = BandwidthProps::with_defaults(BandwidthMode::Annotate, namespace(&[]));
+        let mut obj = pod(
+            serde_json::json!({
+                "requests": { "networking.k8s.io/egress-bandwidth": "2M" },
+                "limits": { "networking.k8s.io/egress-bandwidth": "5M" },
+            }),
+            serde_json::json!({}),
+        );
+        obj.data["spec"]["resources"] = serde_json::json!({
+            "limits": { "networking.k8s.io/egress-bandwidth": "3M" },
+        });
+
+        let (patched, _) = patched(&obj, &props);
+
+        // The pod doesn't declare a request, thus the containers' one applies
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/egress-request"],
+            "2M"
+        );
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/egress-bandwidth"],
+            "3M"
+        );
+    }
+
+    #[test]
+    fn test_overhead_adds_to_requests_and_limits() {
+        let props = BandwidthProps::with_defaults(BandwidthMode::Annotate, namespace(&[]));
+        let mut obj = pod(
+            serde_json::json!({
+                "requests": {
+                    "networking.k8s.io/egress-bandwidth": "1M",
+                    "networking.k8s.io/ingress-bandwidth": "1M",
+                },
+                "limits": { "networking.k8s.io/egress-bandwidth": "2M" },
+            }),
+            serde_json::json!({}),
+        );
+        obj.data["spec"]["overhead"] = serde_json::json!({
+            "networking.k8s.io/egress-bandwidth": "500k",
+            "networking.k8s.io/ingress-bandwidth": "500k",
+        });
+
+        let (patched, _) = patched(&obj, &props);
+
+        // Only the egress bandwidth has a limit to add the overhead to
+        assert_eq!(
+            patched["metadata"]["annotations"],
+            serde_json::json!({
+                "nba-admission": "true",
+                "kubernetes.io/egress-request": "1500k",
+                "kubernetes.io/egress-bandwidth": "2500k",
+                "kubernetes.io/ingress-request": "1500k",
+            })
+        );
+    }
+
+    #[test]
+    fn test_strip_pod_resources() {
+        let props = BandwidthProps::with_defaults(BandwidthMode::Strip, namespace(&[]));
+        let mut obj = pod(serde_json::json!({}), serde_json::json!({}));
+        obj.data["spec"]["resources"] = serde_json::json!({
+            "requests": { "networking.k8s.io/egress-bandwidth": "1M", "cpu": "1" },
+            "limits": { "networking.k8s.io/egress-bandwidth": "2M" },
+        });
+
+        let (patched, _) = patched(&obj, &props);
+
+        assert_eq!(
+            patched["spec"]["resources"],
+            serde_json::json!({ "requests": { "cpu": "1" }, "limits": {} })
+        );
+        assert_eq!(
+            patched["metadata"]["annotations"]["kubernetes.io/egress-bandwidth"],
+            "2M"
+        );
+    }
+
+    #[test]
+    fn test_overwrite_pod_resources() {
+        let props = BandwidthProps::with_defaults(BandwidthMode::Overwrite, namespace(&[]));
+        let mut obj = pod(serde_json::json!({}), serde_json::json!({}));
+        obj.data["spec"]["resources"] = serde_json::json!({
+            "requests": { "networking.k8s.io/egress-bandwidth": "1M" },
+            "limits": { "networking.k8s.io/egress-bandwidth": "2M" },
+        });
+
+        let (patched, _) = patched(&obj, &props);
+
+        assert_eq!(
+            patched["spec"]["resources"]["limits"],
+            serde_json::json!({ "networking.k8s.io/egress-bandwidth": "1M" })
+        );
+    }
+
+    #[test]
+    fn test_ratio_overflow() {
+        let props = BandwidthProps::with_defaults(BandwidthMode::Ratio, namespace(&[]));
+        let obj = pod(
+            serde_json::json!({
+                "requests": { "networking.k8s.io/egress-bandwidth": "100000000000Ei" },
+            }),
+            serde_json::json!({}),
+        );
+
+        assert_eq!(
+            bandwidth_patches(&obj, None, &props)
+                .unwrap_err()
+                .to_string(),
+            "Egress limit overflowed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_namespace_not_required_by_workload_labels() {
+        let props = BandwidthProps::with_defaults(
+            BandwidthMode::Annotate,
+            NamespaceCache::with_namespaces(Vec::new(), true),
+        );
+        let mut obj = serde_json::to_value(deployment(serde_json::json!({
+            "limits": { "networking.k8s.io/egress-bandwidth": "2M" },
+        })))
+        .unwrap();
+        obj["metadata"]["labels"] =
+            serde_json::json!({ MODE_LABEL: "annotate", SCHEDULER_LABEL: "my-scheduler" });
+
+        let res = admit(obj, Mode::Mutate(props), NamespaceLookupPolicy::Deny).await;
+
+        assert_eq!(res["allowed"], true);
+        assert!(res.get("patch").is_some());
+    }
+
+    #[test]
+    fn test_check_inherited_template() {
+        let props = BandwidthProps::with_defaults(
+            BandwidthMode::Overwrite,
+            namespace(&[(MAX_EGRESS_LABEL, "60M"), (CLAMP_ENFORCEMENT_LABEL, "deny")]),
+        );
+        let obj = deployment(serde_json::json!({
+            "requests": { "networking.k8s.io/egress-bandwidth": "1M" },
+            "limits": { "networking.k8s.io/egress-bandwidth": "2M" },
+        }));
+
+        // The ReplicaSet created from the Deployment inherits its mutated template
+        let (mut replica_set, _) = patched(&obj, &props);
+        replica_set["kind"] = "ReplicaSet".into();
+        replica_set["metadata"]["ownerReferences"] = serde_json::json!([{
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "name": "deployment",
+            "uid": "705ab4f5-6393-11e8-b7cc-42010a800002",
+        }]);
+        let replica_set = object(replica_set);
+
+        assert_eq!(
+            bandwidth_patches(&replica_set, None, &props).unwrap(),
+            (Vec::new(), Vec::new())
+        );
+    }
+
+    #[test]
+    fn test_check_template_claiming_mutation() {
+        let props = BandwidthProps {
+            strict: true,
+            ..BandwidthProps::with_defaults(
+                BandwidthMode::Annotate,
+                namespace(&[(MAX_EGRESS_LABEL, "60M"), (CLAMP_ENFORCEMENT_LABEL, "deny")]),
+            )
+        };
+
+        // Pods carrying the annotation themselves are validated and bounded all the same
+        let obj = pod(
+            serde_json::json!({ "limits": { "networking.k8s.io/egress-bandwidth": "1Kb" } }),
+            serde_json::json!({ "nba-admission": "true" }),
+        );
+
+        assert!(bandwidth_patches(&obj, None, &props)
+            .unwrap_err()
+            .to_string()
+            .starts_with("Invalid bandwidth resources: container \"app\""));
+
+        let obj = pod(
+            serde_json::json!({ "limits": { "networking.k8s.io/egress-bandwidth": "80M" } }),
+            serde_json::json!({ "nba-admission": "true" }),
+        );
+
+        assert_eq!(
+            bandwidth_patches(&obj, None, &props)
+                .unwrap_err()
+                .to_string(),
+            "The egress limit of container \"app\" (80M) exceeds the namespace's maximum of 60M"
+        );
+    }
+
+    #[test]
+    fn test_recompute_changed_template() {
+        let props = BandwidthProps {
+            conflict_policy: ConflictPolicy::Deny,
+            ..BandwidthProps::with_defaults(BandwidthMode::Overwrite, namespace(&[]))
+        };
+        let obj = deployment(serde_json::json!({
+            "requests": { "networking.k8s.io/egress-bandwidth": "1M" },
+            "limits": { "networking.k8s.io/egress-bandwidth": "2M" },
+        }));
+
+        let (created, _) = patched(&obj, &props);
+        let created = object(created);
+
+        // The update raises the limit, taking over the annotations of the previous mutation
+        let mut change = serde_json::to_value(&created).unwrap();
+        change["spec"]["template"]["spec"]["containers"][0]["resources"] = serde_json::json!({
+            "requests": { "networking.k8s.io/egress-bandwidth": "5M" },
+            "limits": { "networking.k8s.io/egress-bandwidth": "50M" },
+        });
+        let (changed, warnings) = updated(&object(change), Some(&created), &props);
+        let template = &changed["spec"]["template"];
+
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(
+            template["spec"]["containers"][0]["resources"]["limits"],
+            serde_json::json!({ "networking.k8s.io/egress-bandwidth": "5M" })
+        );
+        assert_eq!(
+            template["metadata"]["annotations"],
+            serde_json::json!({
+                "nba-admission": "true",
+                "kubernetes.io/egress-request": "5M",
+                "kubernetes.io/egress-bandwidth": "50M",
+            })
+        );
+
+        // Removing the bandwidth removes the annotations computed from it
+        let mut removal = serde_json::to_value(&created).unwrap();
+        removal["spec"]["template"]["spec"]["containers"][0]["resources"] = serde_json::json!({});
+        let (removed, _) = updated(&object(removal), Some(&created), &props);
+
+        assert_eq!(
+            removed["spec"]["template"]["metadata"]["annotations"],
+            serde_json::json!({ "nba-admission": "true" })
+        );
+    }
+
+    #[test]
+    fn test_kube_ovn_profile() {
+        let props = BandwidthProps::with_defaults(
+            BandwidthMode::Annotate,
+            namespace(&[(CNI_PROFILE_LABEL, "kube-ovn")]),
+        );
+        let obj = pod(
+            serde_json::json!({
+                "requests": {
+                    "networking.k8s.io/egress-bandwidth": "5M",
+                    "networking.k8s.io/ingress-bandwidth": "1M",
+                },
+                "limits": {
+                    "networking.k8s.io/egress-bandwidth": "10M",
+                    "networking.k8s.io/ingress-bandwidth": "1500k",
+                },
+            }),
+            serde_json::json!({}),
+        );
+
+        let (patched, warnings) = patched(&obj, &props);
+
+        // Only the limits are written in Mbit/s, regardless of the output format
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(
+            patched["metadata"]["annotations"],
+            serde_json::json!({
+                "nba-admission": "true",
+                "kubernetes.io/egress-request": "5M",
+                "kubernetes.io/ingress-request": "1M",
+                "ovn.kubernetes.io/egress_rate": "10",
+                "ovn.kubernetes.io/ingress_rate": "2",
+            })
+        );
+    }
+
+    #[test]
+    fn test_cilium_profile() {
+        let props = BandwidthProps::with_defaults(
+            BandwidthMode::Annotate,
+            namespace(&[(CNI_PROFILE_LABEL, "cilium")]),
+        );
+        let obj = pod(
+            serde_json::json!({
+                "requests": {
+                    "networking.k8s.io/egress-bandwidth": "10M",
+                    "networking.k8s.io/ingress-bandwidth": "10M",
+                },
+                "limits": {
+                    "networking.k8s.io/egress-bandwidth": "10M",
+                    "networking.k8s.io/ingress-bandwidth": "10M",
+                },
+            }),
+            serde_json::json!({}),
+        );
+
+        let (patched, warnings) = patched(&obj, &props);
+
+        assert_eq!(
+            patched["metadata"]["annotations"],
+            serde_json::json!({
+                "nba-admission": "true",
+                "kubernetes.io/egress-request": "10M",
+                "kubernetes.io/ingress-request": "10M",
+                "kubernetes.io/egress-bandwidth": "10M",
+            })
+        );
+        assert_eq!(
+            warnings,
+            ["the cilium CNI profile does not support ingress bandwidth limits, thus they will not be shaped"]
+        );
+    }
+}
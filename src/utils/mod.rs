@@ -1,4 +1,7 @@
+pub(crate) mod networks;
 pub(crate) mod quantity;
+pub(crate) mod resources;
+pub(crate) mod template;
 
 pub(crate) fn escape_json_pointer(key: &str) -> String {
     key.replace('~', "~0").replace('/', "~1")
@@ -0,0 +1,150 @@
+use serde_json::{Map, Value};
+
+use super::quantity::Quantity;
+
+/// Annotation Multus reads a pod's secondary networks from
+pub(crate) const NETWORKS_ANNOTATION: &str = "k8s.v1.cni.cncf.io/networks";
+
+/// Burst handed to the bandwidth plugin alongside a rate, as it requires both, mirroring the kubelet's "no limit"
+const UNLIMITED_BURST: i64 = i32::MAX as i64;
+
+/// Splits a per-network resource key, written as `PREFIX<network>-egress` or `PREFIX<network>-ingress`,
+/// into the network's name and the direction
+pub(crate) fn network_resource<'a>(key: &'a str, prefix: &str) -> Option<(&'a str, &'static str)> {
+    let key = key.strip_prefix(prefix)?;
+
+    ["egress", "ingress"].into_iter().find_map(|direction| {
+        key.strip_suffix(direction)
+            .and_then(|network| network.strip_suffix('-'))
+            .filter(|network| !network.is_empty())
+            .map(|network| (network, direction))
+    })
+}
+
+/// Parses the network selection elements of a networks annotation, being either a JSON list of objects or a
+/// comma-separated list of `[<namespace>/]<network>[@<interface>]` references
+pub(crate) fn parse_networks(annotation: &str) -> Result<Vec<Map<String, Value>>, String> {
+    let annotation = annotation.trim();
+
+    if annotation.starts_with('[') {
+        return serde_json::from_str(annotation).map_err(|err| err.to_string());
+    }
+
+    annotation
+        .split(',')
+        .map(str::trim)
+        .filter(|reference| !reference.is_empty())
+        .map(|reference| {
+            let (reference, interface) = match reference.split_once('@') {
+                Some((reference, interface)) => (reference, Some(interface)),
+                None => (reference, None),
+            };
+            let (namespace, name) = match reference.split_once('/') {
+                Some((namespace, name)) => (Some(namespace), name),
+                None => (None, reference),
+            };
+
+            if name.is_empty() || namespace == Some("") || interface == Some("") {
+                return Err(format!("malformed network reference {reference:?}"));
+            }
+
+            let mut element = Map::new();
+            element.insert("name".to_owned(), Value::String(name.to_owned()));
+
+            if let Some(namespace) = namespace {
+                element.insert("namespace".to_owned(), Value::String(namespace.to_owned()));
+            }
+
+            if let Some(interface) = interface {
+                element.insert("interface".to_owned(), Value::String(interface.to_owned()));
+            }
+
+            Ok(element)
+        })
+        .collect()
+}
+
+/// Builds the bandwidth `runtimeConfig` of a network selection element, with rates in bits per second
+pub(crate) fn bandwidth_entry(egress: Option<Quantity>, ingress: Option<Quantity>) -> Value {
+    let mut entry = Map::new();
+
+    for (direction, rate) in [("egress", egress), ("ingress", ingress)] {
+        let Some(rate) = rate else {
+            continue;
+        };
+
+        let rate = i64::try_from(rate.scaled_value(0)).unwrap_or(i64::MAX);
+
+        entry.insert(format!("{direction}Rate"), Value::from(rate));
+        entry.insert(format!("{direction}Burst"), Value::from(UNLIMITED_BURST));
+    }
+
+    Value::Object(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::quantity::parse;
+
+    #[test]
+    fn test_network_resource() {
+        let prefix = "bandwidth.nbam.io/";
+
+        assert_eq!(
+            network_resource("bandwidth.nbam.io/storage-net-egress", prefix),
+            Some(("storage-net", "egress"))
+        );
+        assert_eq!(
+            network_resource("bandwidth.nbam.io/storage-net-ingress", prefix),
+            Some(("storage-net", "ingress"))
+        );
+        assert_eq!(network_resource("bandwidth.nbam.io/-egress", prefix), None);
+        assert_eq!(network_resource("bandwidth.nbam.io/storage", prefix), None);
+        assert_eq!(
+            network_resource("networking.k8s.io/egress-bandwidth", prefix),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_network_references() {
+        let networks = parse_networks("storage-net, other/metrics-net@net2").unwrap();
+
+        assert_eq!(
+            Value::from(networks),
+            serde_json::json!([
+                { "name": "storage-net" },
+                { "name": "metrics-net", "namespace": "other", "interface": "net2" },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_network_elements() {
+        let networks =
+            parse_networks(r#"[{"name": "storage-net", "ips": ["10.1.1.1/24"]}]"#).unwrap();
+
+        assert_eq!(networks.len(), 1);
+        assert_eq!(networks[0]["name"], "storage-net");
+        assert_eq!(networks[0]["ips"], serde_json::json!(["10.1.1.1/24"]));
+    }
+
+    #[test]
+    fn test_parse_malformed_networks() {
+        for annotation in ["[{", "storage-net@", "/storage-net", "[1]"] {
+            assert!(
+                parse_networks(annotation).is_err(),
+                "{annotation:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_bandwidth_entry() {
+        assert_eq!(
+            bandwidth_entry(Some(parse("10M").unwrap()), None),
+            serde_json::json!({ "egressRate": 10_000_000, "egressBurst": 2_147_483_647 })
+        );
+    }
+}
@@ -1,5 +1,383 @@
-use color_eyre::Result;
-use regex::Regex;
+use std::{
+    cmp::Ordering,
+    fmt,
+    iter::Sum,
+    ops::{Add, Mul, Sub},
+    str::FromStr,
+};
+
+/// Number of base 10 digits kept after the decimal point, mirroring apimachinery's nano precision
+const NANO_DIGITS: i32 = 9;
+/// Number of nano units in a single unit
+const NANOS_PER_UNIT: i128 = 1_000_000_000;
+/// Maximum number of significant digits an i128 mantissa can hold without overflowing
+const MAX_DIGITS: usize = 38;
+
+const BINARY_SUFFIXES: [&str; 7] = ["", "Ki", "Mi", "Gi", "Ti", "Pi", "Ei"];
+const DECIMAL_SUFFIXES: [(i32, &str); 10] = [
+    (-9, "n"),
+    (-6, "u"),
+    (-3, "m"),
+    (0, ""),
+    (3, "k"),
+    (6, "M"),
+    (9, "G"),
+    (12, "T"),
+    (15, "P"),
+    (18, "E"),
+];
+
+/// The format a `Quantity` was written in and is serialized back to, see `resource.Format` in apimachinery
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// e.g., 12Mi (12 * 2^20)
+    BinarySI,
+    /// e.g., 12e6
+    DecimalExponent,
+    /// e.g., 12M (12 * 10^6)
+    DecimalSI,
+}
+
+/// An exact, fixed-point representation of a Kubernetes `Quantity`, modeled on apimachinery's `resource.Quantity`.
+///
+/// Values are stored as a signed number of nano units, the smallest precision apimachinery retains.
+/// Parsing rounds any remaining fraction up, away from zero, just like apimachinery does.
+///
+/// Comparisons only take the value into account, thus `1Ki` equals `1024`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Quantity {
+    nanos: i128,
+    format: Format,
+}
+
+impl Quantity {
+    pub(crate) const ZERO: Quantity = Quantity {
+        nanos: 0,
+        format: Format::DecimalSI,
+    };
+
+    /// Adds two quantities, returning `None` on overflow.
+    ///
+    /// The result keeps the format of `self`, unless `self` is zero, in which case the format of `other` is used.
+    pub(crate) fn checked_add(self, other: Quantity) -> Option<Quantity> {
+        Some(Quantity {
+            nanos: self.nanos.checked_add(other.nanos)?,
+            format: self.result_format(other),
+        })
+    }
+
+    /// Subtracts `other` from `self`, returning `None` on overflow.
+    ///
+    /// The result format is chosen just like in `checked_add`.
+    pub(crate) fn checked_sub(self, other: Quantity) -> Option<Quantity> {
+        Some(Quantity {
+            nanos: self.nanos.checked_sub(other.nanos)?,
+            format: self.result_format(other),
+        })
+    }
+
+    /// Multiplies the quantity by a ratio, returning `None` on overflow.
+    ///
+    /// Any fraction below the nano precision is rounded up, away from zero, just like when parsing.
+    pub(crate) fn checked_mul(self, ratio: Ratio) -> Option<Quantity> {
+        let product = self.nanos.checked_mul(ratio.numerator)?;
+        let nanos = product / ratio.denominator;
+        let remainder = product % ratio.denominator;
+
+        Some(Quantity {
+            nanos: match remainder.signum() {
+                1 => nanos.checked_add(1)?,
+                -1 => nanos.checked_sub(1)?,
+                _ => nanos,
+            },
+            format: self.format,
+        })
+    }
+
+    /// Returns the same value, serialized using the given format
+    pub(crate) fn with_format(self, format: Format) -> Quantity {
+        Quantity {
+            nanos: self.nanos,
+            format,
+        }
+    }
+
+    /// Returns the value in units of `10^exponent`, rounded up, away from zero, like apimachinery's `Quantity.ScaledValue()`
+    pub(crate) fn scaled_value(self, exponent: i32) -> i128 {
+        let shift = exponent + NANO_DIGITS;
+
+        if shift < 0 {
+            return self
+                .nanos
+                .saturating_mul(10_i128.saturating_pow(shift.unsigned_abs()));
+        }
+
+        match 10_i128.checked_pow(shift.unsigned_abs()) {
+            Some(divisor) => {
+                self.nanos / divisor + self.nanos.signum() * i128::from(self.nanos % divisor != 0)
+            }
+            None => self.nanos.signum(),
+        }
+    }
+
+    fn result_format(self, other: Quantity) -> Format {
+        if self.nanos == 0 {
+            other.format
+        } else {
+            self.format
+        }
+    }
+
+    /// Builds a quantity from its parsed components.
+    ///
+    /// The value is `(-1 if negative) * <integer>.<fraction> * 10^decimal_exponent * 2^binary_exponent`.
+    fn from_parts(
+        negative: bool,
+        integer: &str,
+        fraction: &str,
+        decimal_exponent: i32,
+        binary_exponent: u32,
+        format: Format,
+    ) -> Option<Quantity> {
+        let fraction = fraction.trim_end_matches('0');
+        let digits = format!("{integer}{fraction}");
+        let digits = digits.trim_start_matches('0');
+
+        if digits.len() > MAX_DIGITS {
+            return None;
+        }
+
+        let mantissa = if digits.is_empty() {
+            0
+        } else {
+            digits.parse::<i128>().ok()?
+        };
+        let mantissa = mantissa.checked_mul(1_i128.checked_shl(binary_exponent)?)?;
+
+        let scale = decimal_exponent
+            .checked_sub(i32::try_from(fraction.len()).ok()?)?
+            .checked_add(NANO_DIGITS)?;
+
+        let nanos = if scale >= 0 {
+            mantissa.checked_mul(10_i128.checked_pow(scale.unsigned_abs())?)?
+        } else {
+            match 10_i128.checked_pow(scale.unsigned_abs()) {
+                // Round any fraction below the nano precision up
+                Some(divisor) => mantissa / divisor + i128::from(mantissa % divisor != 0),
+                None => i128::from(mantissa != 0),
+            }
+        };
+
+        Some(Quantity {
+            nanos: if negative { -nanos } else { nanos },
+            format,
+        })
+    }
+}
+
+impl PartialEq for Quantity {
+    fn eq(&self, other: &Self) -> bool {
+        self.nanos == other.nanos
+    }
+}
+
+impl Eq for Quantity {}
+
+impl PartialOrd for Quantity {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Quantity {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.nanos.cmp(&other.nanos)
+    }
+}
+
+impl Add for Quantity {
+    type Output = Result<Quantity, QuantityError>;
+
+    fn add(self, other: Quantity) -> Self::Output {
+        self.checked_add(other).ok_or(QuantityError::Overflow)
+    }
+}
+
+impl Sub for Quantity {
+    type Output = Result<Quantity, QuantityError>;
+
+    fn sub(self, other: Quantity) -> Self::Output {
+        self.checked_sub(other).ok_or(QuantityError::Overflow)
+    }
+}
+
+impl Mul<Ratio> for Quantity {
+    type Output = Result<Quantity, QuantityError>;
+
+    fn mul(self, ratio: Ratio) -> Self::Output {
+        self.checked_mul(ratio).ok_or(QuantityError::Overflow)
+    }
+}
+
+/// Sums up quantities, failing on overflow instead of saturating
+impl Sum<Quantity> for Result<Quantity, QuantityError> {
+    fn sum<I: Iterator<Item = Quantity>>(mut iter: I) -> Self {
+        iter.try_fold(Quantity::ZERO, |sum, quantity| sum + quantity)
+    }
+}
+
+impl<'a> Sum<&'a Quantity> for Result<Quantity, QuantityError> {
+    fn sum<I: Iterator<Item = &'a Quantity>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
+/// An exact factor to scale quantities by, e.g., `2`, `1.5` or `750m`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Ratio {
+    numerator: i128,
+    denominator: i128,
+}
+
+impl Ratio {
+    pub(crate) const fn new(numerator: i128, denominator: i128) -> Ratio {
+        Ratio {
+            numerator,
+            denominator,
+        }
+    }
+
+    /// Whether scaling by the ratio keeps the sign of quantities
+    pub(crate) fn is_positive(self) -> bool {
+        self.numerator.signum() * self.denominator.signum() > 0
+    }
+}
+
+impl FromStr for Ratio {
+    type Err = QuantityError;
+
+    /// Parses a ratio using the quantity grammar, keeping the same nano precision
+    fn from_str(ratio: &str) -> Result<Self, Self::Err> {
+        Ok(Ratio {
+            numerator: parse(ratio)?.nanos,
+            denominator: NANOS_PER_UNIT,
+        })
+    }
+}
+
+impl fmt::Display for Quantity {
+    /// Writes the quantity in its canonical form, as apimachinery's `Quantity.String()` would
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.nanos == 0 {
+            return f.write_str("0");
+        }
+
+        // Binary suffixes are only used for whole numbers of at least 1Ki, everything else falls back to decimal SI
+        let format = match self.format {
+            Format::BinarySI
+                if self.nanos.abs() < 1024 * NANOS_PER_UNIT || self.nanos % NANOS_PER_UNIT != 0 =>
+            {
+                Format::DecimalSI
+            }
+            format => format,
+        };
+
+        if format == Format::BinarySI {
+            let mut amount = self.nanos / NANOS_PER_UNIT;
+            let mut exponent = 0;
+
+            while exponent < BINARY_SUFFIXES.len() - 1 && amount % 1024 == 0 {
+                amount /= 1024;
+                exponent += 1;
+            }
+
+            return write!(f, "{amount}{}", BINARY_SUFFIXES[exponent]);
+        }
+
+        let mut amount = self.nanos;
+        let mut exponent = -NANO_DIGITS;
+
+        while amount % 10 == 0 {
+            amount /= 10;
+            exponent += 1;
+        }
+
+        // Make sure the exponent is a multiple of 3, this never overflows as at least as many factors were removed above
+        match exponent.rem_euclid(3) {
+            1 => {
+                amount *= 10;
+                exponent -= 1;
+            }
+            2 => {
+                amount *= 100;
+                exponent -= 2;
+            }
+            _ => {}
+        }
+
+        if format == Format::DecimalExponent {
+            return if exponent == 0 {
+                write!(f, "{amount}")
+            } else {
+                write!(f, "{amount}e{exponent}")
+            };
+        }
+
+        // Decimal SI suffixes end at E, thus larger exponents get folded back into the amount
+        let (largest_exponent, largest_suffix) = DECIMAL_SUFFIXES[DECIMAL_SUFFIXES.len() - 1];
+        match DECIMAL_SUFFIXES
+            .iter()
+            .find(|(suffix_exponent, _)| *suffix_exponent == exponent)
+        {
+            Some((_, suffix)) => write!(f, "{amount}{suffix}"),
+            None => {
+                let padding = "0".repeat((exponent - largest_exponent).unsigned_abs() as usize);
+
+                write!(f, "{amount}{padding}{largest_suffix}")
+            }
+        }
+    }
+}
+
+/// The reason a quantity could not be parsed, carrying the byte offset at which parsing failed, or computed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum QuantityError {
+    /// The input was empty
+    Empty,
+    /// A digit was expected at the given offset
+    ExpectedDigit { offset: usize },
+    /// The exponent was followed by further characters starting at the given offset
+    UnexpectedCharacter { offset: usize },
+    /// The suffix starting at the given offset is neither a binary SI, decimal SI, nor decimal exponent suffix
+    UnknownSuffix { offset: usize, suffix: String },
+    /// The number starting at the given offset cannot be represented
+    OutOfRange { offset: usize },
+    /// An arithmetic operation exceeded the representable range
+    Overflow,
+}
+
+impl fmt::Display for QuantityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuantityError::Empty => write!(f, "quantity is empty"),
+            QuantityError::ExpectedDigit { offset } => {
+                write!(f, "expected a digit at byte {offset}")
+            }
+            QuantityError::UnexpectedCharacter { offset } => {
+                write!(f, "unexpected character at byte {offset}")
+            }
+            QuantityError::UnknownSuffix { offset, suffix } => {
+                write!(f, "unknown suffix {suffix:?} at byte {offset}")
+            }
+            QuantityError::OutOfRange { offset } => {
+                write!(f, "number at byte {offset} is out of range")
+            }
+            QuantityError::Overflow => write!(f, "quantity overflowed"),
+        }
+    }
+}
+
+impl std::error::Error for QuantityError {}
 
 /// Parses a Kubernetes `Quantity` as defined in: https://github.com/kubernetes/apimachinery/blob/master/pkg/api/resource/quantity.go#L31
 ///
@@ -26,42 +404,129 @@ use regex::Regex;
 ///
 /// <decimalExponent> ::= "e" <signedNumber> | "E" <signedNumber>
 /// ```
-pub(crate) fn parse(quantity: &str) -> Result<f64> {
-    let regex = Regex::new(r"([[:alpha:]]{1,2}$)")?;
+///
+/// Just like apimachinery, the `n` and `u` decimal SI suffixes are accepted as well, as canonical quantities may use them,
+/// and the number of a decimal exponent has to be an integer.
+///
+/// Unlike apimachinery, inputs outside of the grammar, such as "", "-", "." or "Ki", are rejected instead of being read as zero.
+pub(crate) fn parse(quantity: &str) -> Result<Quantity, QuantityError> {
+    if quantity.is_empty() {
+        return Err(QuantityError::Empty);
+    }
 
-    let Some(suffix) = regex.captures(quantity) else {
-        return Ok(quantity.parse()?);
+    let mut offset = 0;
+
+    // <sign>
+    let negative = match quantity.as_bytes()[0] {
+        b'-' => {
+            offset += 1;
+            true
+        }
+        b'+' => {
+            offset += 1;
+            false
+        }
+        _ => false,
     };
 
-    let Some(suffix) = suffix.get(0) else {
-        return Err(color_eyre::eyre::eyre!("Could not determine quantity suffix"));
+    // <number>
+    let number_offset = offset;
+    let integer = take_digits(quantity, &mut offset);
+    let fraction = if quantity.as_bytes().get(offset) == Some(&b'.') {
+        offset += 1;
+        take_digits(quantity, &mut offset)
+    } else {
+        ""
     };
 
-    let multiplier: f64 = match suffix.as_str() {
-        "Ki" => 1024.0,
-        "Mi" => 1024_f64.powi(2),
-        "Gi" => 1024_f64.powi(3),
-        "Ti" => 1024_f64.powi(4),
-        "Pi" => 1024_f64.powi(5),
-        "Ei" => 1024_f64.powi(6),
-        "k" => 1000.0,
-        "M" => 1000_f64.powi(2),
-        "G" => 1000_f64.powi(3),
-        "T" => 1000_f64.powi(4),
-        "P" => 1000_f64.powi(5),
-        "E" => 1000_f64.powi(6),
-        "m" => 1.0 / 1000.0,
+    if integer.is_empty() && fraction.is_empty() {
+        return Err(QuantityError::ExpectedDigit { offset });
+    }
+
+    // <suffix>
+    let suffix_offset = offset;
+    let suffix = &quantity[offset..];
+    let (format, decimal_exponent, binary_exponent) = match suffix {
+        "Ki" => (Format::BinarySI, 0, 10),
+        "Mi" => (Format::BinarySI, 0, 20),
+        "Gi" => (Format::BinarySI, 0, 30),
+        "Ti" => (Format::BinarySI, 0, 40),
+        "Pi" => (Format::BinarySI, 0, 50),
+        "Ei" => (Format::BinarySI, 0, 60),
+        "n" => (Format::DecimalSI, -9, 0),
+        "u" => (Format::DecimalSI, -6, 0),
+        "m" => (Format::DecimalSI, -3, 0),
+        "" => (Format::DecimalSI, 0, 0),
+        "k" => (Format::DecimalSI, 3, 0),
+        "M" => (Format::DecimalSI, 6, 0),
+        "G" => (Format::DecimalSI, 9, 0),
+        "T" => (Format::DecimalSI, 12, 0),
+        "P" => (Format::DecimalSI, 15, 0),
+        "E" => (Format::DecimalSI, 18, 0),
+        _ if suffix.starts_with(['e', 'E']) => (
+            Format::DecimalExponent,
+            parse_exponent(quantity, offset + 1)?,
+            0,
+        ),
         _ => {
-            return Err(color_eyre::eyre::eyre!(
-                "Unknown quantity suffix used: {suffix:?}"
-            ));
+            return Err(QuantityError::UnknownSuffix {
+                offset,
+                suffix: suffix.to_owned(),
+            })
         }
     };
 
-    let quantity = quantity.replace(suffix.as_str(), "");
-    let quantity = quantity.parse::<f64>()?;
+    Quantity::from_parts(
+        negative,
+        integer,
+        fraction,
+        decimal_exponent,
+        binary_exponent,
+        format,
+    )
+    .ok_or(QuantityError::OutOfRange {
+        // Values out of range are due to the decimal exponent's number, if any, otherwise due to the number itself
+        offset: match format {
+            Format::DecimalExponent => suffix_offset + 1,
+            _ => number_offset,
+        },
+    })
+}
+
+/// Parses the `<sign><digits>` of a decimal exponent starting at `offset` up until the end of the quantity
+fn parse_exponent(quantity: &str, offset: usize) -> Result<i32, QuantityError> {
+    let mut end = offset;
 
-    Ok(quantity * multiplier)
+    if matches!(quantity.as_bytes().get(end), Some(b'+' | b'-')) {
+        end += 1;
+    }
+
+    if take_digits(quantity, &mut end).is_empty() {
+        return Err(QuantityError::ExpectedDigit { offset: end });
+    }
+
+    if end < quantity.len() {
+        return Err(QuantityError::UnexpectedCharacter { offset: end });
+    }
+
+    quantity[offset..]
+        .parse()
+        .map_err(|_| QuantityError::OutOfRange { offset })
+}
+
+/// Returns the run of ASCII digits starting at `offset`, advancing `offset` past it
+fn take_digits<'a>(quantity: &'a str, offset: &mut usize) -> &'a str {
+    let start = *offset;
+
+    while quantity
+        .as_bytes()
+        .get(*offset)
+        .is_some_and(u8::is_ascii_digit)
+    {
+        *offset += 1;
+    }
+
+    &quantity[start..*offset]
 }
 
 #[cfg(test)]
@@ -74,7 +539,7 @@ mod tests {
 
         let result = parse(quantity);
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), 750.0);
+        assert_eq!(result.unwrap().to_string(), "750");
     }
 
     #[test]
@@ -83,7 +548,7 @@ mod tests {
 
         let result = parse(quantity);
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), 1000000.0);
+        assert_eq!(result.unwrap().to_string(), "1M");
     }
 
     #[test]
@@ -92,7 +557,7 @@ mod tests {
 
         let result = parse(quantity);
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), 1250000000.0);
+        assert_eq!(result.unwrap().to_string(), "1250M");
     }
 
     #[test]
@@ -101,6 +566,281 @@ mod tests {
 
         let result = parse(quantity);
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), 576716800.0);
+        assert_eq!(result.unwrap().to_string(), "550Mi");
+    }
+
+    #[test]
+    fn test_parse_decimal_exponent() {
+        let quantity = "1.25e+9";
+
+        let result = parse(quantity);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().to_string(), "1250e6");
+    }
+
+    #[test]
+    fn test_parse_binary_fraction_falls_back_to_decimal() {
+        let quantity = "0.1Ki";
+
+        let result = parse(quantity);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().to_string(), "102400m");
+    }
+
+    #[test]
+    fn test_parse_rounds_up_to_nano() {
+        let quantity = "0.0000000001";
+
+        let result = parse(quantity);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().to_string(), "1n");
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        assert_eq!(parse("").unwrap_err(), QuantityError::Empty);
+    }
+
+    #[test]
+    fn test_parse_unknown_suffix() {
+        assert_eq!(
+            parse("1.5Kb").unwrap_err(),
+            QuantityError::UnknownSuffix {
+                offset: 3,
+                suffix: "Kb".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_does_not_strip_repeated_suffix() {
+        assert_eq!(
+            parse("1MM").unwrap_err(),
+            QuantityError::UnknownSuffix {
+                offset: 1,
+                suffix: "MM".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_exponent_without_digits() {
+        assert_eq!(
+            parse("1e+").unwrap_err(),
+            QuantityError::ExpectedDigit { offset: 3 }
+        );
+    }
+
+    #[test]
+    fn test_parse_exponent_with_fraction() {
+        assert_eq!(
+            parse("1e1.5").unwrap_err(),
+            QuantityError::UnexpectedCharacter { offset: 3 }
+        );
+    }
+
+    #[test]
+    fn test_parse_exponent_out_of_range() {
+        assert_eq!(
+            parse("1e99").unwrap_err(),
+            QuantityError::OutOfRange { offset: 2 }
+        );
+        assert_eq!(
+            parse("-1.5e99").unwrap_err(),
+            QuantityError::OutOfRange { offset: 5 }
+        );
+    }
+
+    #[test]
+    fn test_parse_number_out_of_range() {
+        assert_eq!(
+            parse("-1000000000000000000000000Ei").unwrap_err(),
+            QuantityError::OutOfRange { offset: 1 }
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_numbers_without_digits() {
+        // apimachinery reads all of these as zero, yet none of them match the grammar
+        for (quantity, offset) in [
+            ("-", 1),
+            ("+", 1),
+            (".", 1),
+            ("-.", 2),
+            ("Ki", 0),
+            ("e3", 0),
+        ] {
+            assert_eq!(
+                parse(quantity).unwrap_err(),
+                QuantityError::ExpectedDigit { offset },
+                "{quantity:?}"
+            );
+        }
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Fixtures {
+        valid: Vec<ValidFixture>,
+        invalid: Vec<String>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct ValidFixture {
+        input: String,
+        canonical: String,
+    }
+
+    /// Parse results of apimachinery's `resource.ParseQuantity` and `Quantity.String()`
+    fn fixtures() -> Fixtures {
+        serde_json::from_str(include_str!("fixtures/quantities.json")).unwrap()
+    }
+
+    #[test]
+    fn test_parse_matches_apimachinery_canonical_form() {
+        for fixture in fixtures().valid {
+            let result = parse(&fixture.input);
+
+            assert!(result.is_ok(), "{:?}: {result:?}", fixture.input);
+            assert_eq!(
+                result.unwrap().to_string(),
+                fixture.canonical,
+                "{:?}",
+                fixture.input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_canonical_form_round_trips() {
+        for fixture in fixtures().valid {
+            let result = parse(&fixture.canonical);
+
+            assert!(result.is_ok(), "{:?}: {result:?}", fixture.canonical);
+            assert_eq!(result.unwrap().to_string(), fixture.canonical);
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_apimachinery_errors() {
+        for input in fixtures().invalid {
+            assert!(parse(&input).is_err(), "{input:?}");
+        }
+    }
+
+    #[test]
+    fn test_add_binary_without_drift() {
+        let result = parse("1Ei")
+            .unwrap()
+            .checked_add(parse("1Ei").unwrap())
+            .unwrap();
+
+        assert_eq!(result.to_string(), "2Ei");
+    }
+
+    #[test]
+    fn test_add_keeps_format_of_first_non_zero_operand() {
+        let result = parse("0")
+            .unwrap()
+            .checked_add(parse("1.5Gi").unwrap())
+            .unwrap();
+
+        assert_eq!(result.to_string(), "1536Mi");
+    }
+
+    #[test]
+    fn test_with_format() {
+        assert_eq!(
+            parse("1048576")
+                .unwrap()
+                .with_format(Format::BinarySI)
+                .to_string(),
+            "1Mi"
+        );
+        assert_eq!(
+            parse("1Mi")
+                .unwrap()
+                .with_format(Format::DecimalSI)
+                .to_string(),
+            "1048576"
+        );
+    }
+
+    #[test]
+    fn test_scaled_value() {
+        assert_eq!(parse("2500m").unwrap().scaled_value(0), 3);
+        assert_eq!(parse("1.25G").unwrap().scaled_value(6), 1250);
+        assert_eq!(parse("1Mi").unwrap().scaled_value(6), 2);
+        assert_eq!(parse("-1.5").unwrap().scaled_value(0), -2);
+        assert_eq!(parse("1").unwrap().scaled_value(-3), 1000);
+    }
+
+    #[test]
+    fn test_compare_across_formats() {
+        assert_eq!(parse("1Ki").unwrap(), parse("1024").unwrap());
+        assert!(parse("1M").unwrap() < parse("1Mi").unwrap());
+        assert_eq!(
+            parse("1G").unwrap().max(parse("999M").unwrap()).to_string(),
+            "1G"
+        );
+        assert_eq!(
+            parse("1G").unwrap().min(parse("999M").unwrap()).to_string(),
+            "999M"
+        );
+    }
+
+    #[test]
+    fn test_sub() {
+        let result = parse("1Gi").unwrap() - parse("512Mi").unwrap();
+
+        assert_eq!(result.unwrap().to_string(), "512Mi");
+    }
+
+    #[test]
+    fn test_sum() {
+        let result: Result<Quantity, QuantityError> = ["1.25G", "750M", "500m"]
+            .into_iter()
+            .map(|quantity| parse(quantity).unwrap())
+            .sum();
+
+        assert_eq!(result.unwrap().to_string(), "2000000000500m");
+    }
+
+    #[test]
+    fn test_sum_detects_overflow() {
+        let result: Result<Quantity, QuantityError> = ["100000000000Ei", "100000000000Ei"]
+            .into_iter()
+            .map(|quantity| parse(quantity).unwrap())
+            .sum();
+
+        assert_eq!(result.unwrap_err(), QuantityError::Overflow);
+    }
+
+    #[test]
+    fn test_mul_ratio() {
+        let ratio: Ratio = "1.5".parse().unwrap();
+
+        assert_eq!((parse("2M").unwrap() * ratio).unwrap().to_string(), "3M");
+        assert_eq!(
+            (parse("1Gi").unwrap() * ratio).unwrap().to_string(),
+            "1536Mi"
+        );
+    }
+
+    #[test]
+    fn test_mul_ratio_rounds_up() {
+        let ratio: Ratio = "333333334n".parse().unwrap();
+
+        assert_eq!(
+            parse("3n").unwrap().checked_mul(ratio).unwrap().to_string(),
+            "2n"
+        );
+    }
+
+    #[test]
+    fn test_ratio_is_positive() {
+        assert!("2".parse::<Ratio>().unwrap().is_positive());
+        assert!("1m".parse::<Ratio>().unwrap().is_positive());
+        assert!(!"0".parse::<Ratio>().unwrap().is_positive());
+        assert!(!"-1.5".parse::<Ratio>().unwrap().is_positive());
     }
 }
@@ -0,0 +1,125 @@
+use super::quantity::{Quantity, QuantityError};
+
+/// How a container contributes to a pod's effective resources
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ContainerKind {
+    /// Regular container, running for the pod's entire lifetime
+    App,
+    /// Init container, running to completion before the next one starts
+    Init,
+    /// Restartable init container (`restartPolicy: Always`), running alongside all subsequent containers
+    Sidecar,
+}
+
+/// Computes a pod's effective request or limit of a single resource, just like the kubelet does:
+///
+/// `max(sum(app containers) + sum(sidecars), max(each init container + sidecars started before it))`
+///
+/// Init containers and sidecars have to be passed in their declaration order, while containers not declaring
+/// the resource count as zero, thus declared quantities have to be positive. Returns `None` if none of the
+/// containers declares the resource.
+pub(crate) fn effective(
+    containers: impl IntoIterator<Item = (ContainerKind, Option<Quantity>)>,
+) -> Result<Option<Quantity>, QuantityError> {
+    let mut declared = false;
+    let mut app = Quantity::ZERO;
+    let mut sidecars = Quantity::ZERO;
+    let mut init = Quantity::ZERO;
+
+    for (kind, quantity) in containers {
+        let Some(quantity) = quantity else {
+            continue;
+        };
+
+        declared = true;
+
+        match kind {
+            ContainerKind::App => app = (app + quantity)?,
+            ContainerKind::Init => init = init.max((sidecars + quantity)?),
+            ContainerKind::Sidecar => {
+                sidecars = (sidecars + quantity)?;
+                init = init.max(sidecars);
+            }
+        }
+    }
+
+    if !declared {
+        return Ok(None);
+    }
+
+    Ok(Some((app + sidecars)?.max(init)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::quantity::parse;
+
+    fn quantity(quantity: &str) -> Option<Quantity> {
+        Some(parse(quantity).unwrap())
+    }
+
+    #[test]
+    fn test_effective_undeclared() {
+        let result = effective([(ContainerKind::App, None), (ContainerKind::Init, None)]);
+
+        assert_eq!(result.unwrap(), None);
+    }
+
+    #[test]
+    fn test_effective_sums_app_containers() {
+        let result = effective([
+            (ContainerKind::App, quantity("1M")),
+            (ContainerKind::App, None),
+            (ContainerKind::App, quantity("2M")),
+        ]);
+
+        assert_eq!(result.unwrap().unwrap().to_string(), "3M");
+    }
+
+    #[test]
+    fn test_effective_init_container_exceeds_app_containers() {
+        let result = effective([
+            (ContainerKind::Init, quantity("10M")),
+            (ContainerKind::App, quantity("1M")),
+            (ContainerKind::App, quantity("2M")),
+        ]);
+
+        assert_eq!(result.unwrap().unwrap().to_string(), "10M");
+    }
+
+    #[test]
+    fn test_effective_sidecars_add_to_app_containers() {
+        let result = effective([
+            (ContainerKind::Init, quantity("3M")),
+            (ContainerKind::Sidecar, quantity("1M")),
+            (ContainerKind::App, quantity("2M")),
+        ]);
+
+        assert_eq!(result.unwrap().unwrap().to_string(), "3M");
+    }
+
+    #[test]
+    fn test_effective_sidecars_started_before_init_container() {
+        let result = effective([
+            (ContainerKind::Sidecar, quantity("1M")),
+            (ContainerKind::Init, quantity("3M")),
+            (ContainerKind::Sidecar, quantity("5M")),
+            (ContainerKind::App, quantity("2M")),
+        ]);
+
+        // The init container runs alongside the first sidecar only (4M), while the app container runs alongside both (8M)
+        assert_eq!(result.unwrap().unwrap().to_string(), "8M");
+    }
+
+    #[test]
+    fn test_effective_init_container_alongside_sidecar() {
+        let result = effective([
+            (ContainerKind::Sidecar, quantity("1M")),
+            (ContainerKind::Init, quantity("10M")),
+            (ContainerKind::App, quantity("2M")),
+        ]);
+
+        assert_eq!(result.unwrap().unwrap().to_string(), "11M");
+    }
+}
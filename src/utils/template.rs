@@ -0,0 +1,377 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use kube::{core::DynamicObject, ResourceExt};
+
+/// Locations of the pod templates embedded in the built-in workloads, as `(apiVersion, kind, template pointer)`
+const BUILT_IN_TEMPLATES: [(&str, &str, &str); 9] = [
+    ("v1", "Pod", ""),
+    ("v1", "PodTemplate", "/template"),
+    ("v1", "ReplicationController", "/spec/template"),
+    ("apps/v1", "Deployment", "/spec/template"),
+    ("apps/v1", "StatefulSet", "/spec/template"),
+    ("apps/v1", "DaemonSet", "/spec/template"),
+    ("apps/v1", "ReplicaSet", "/spec/template"),
+    ("batch/v1", "Job", "/spec/template"),
+    ("batch/v1", "CronJob", "/spec/jobTemplate/spec/template"),
+];
+
+/// Locates the pod template of a custom resource, written as `GROUP/VERSION/KIND=SPEC_POINTER:METADATA_POINTER`,
+/// e.g., `argoproj.io/v1alpha1/Rollout=/spec/template/spec:/spec/template/metadata`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PodTemplateLocator {
+    api_version: String,
+    kind: String,
+    spec_pointer: String,
+    metadata_pointer: String,
+}
+
+impl FromStr for PodTemplateLocator {
+    type Err = String;
+
+    fn from_str(locator: &str) -> Result<Self, Self::Err> {
+        let (gvk, pointers) = locator
+            .split_once('=')
+            .ok_or("expected GROUP/VERSION/KIND=SPEC_POINTER:METADATA_POINTER")?;
+        let (api_version, kind) = gvk
+            .rsplit_once('/')
+            .ok_or_else(|| format!("expected GROUP/VERSION/KIND, got {gvk:?}"))?;
+        let (spec_pointer, metadata_pointer) = pointers
+            .split_once(':')
+            .ok_or_else(|| format!("expected SPEC_POINTER:METADATA_POINTER, got {pointers:?}"))?;
+
+        if api_version.is_empty() || kind.is_empty() {
+            return Err(format!("expected GROUP/VERSION/KIND, got {gvk:?}"));
+        }
+
+        for pointer in [spec_pointer, metadata_pointer] {
+            if !pointer.starts_with('/') {
+                return Err(format!("expected a JSON pointer, got {pointer:?}"));
+            }
+        }
+
+        Ok(PodTemplateLocator {
+            api_version: api_version.to_owned(),
+            kind: kind.to_owned(),
+            spec_pointer: spec_pointer.to_owned(),
+            metadata_pointer: metadata_pointer.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for PodTemplateLocator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}={}:{}",
+            self.api_version, self.kind, self.spec_pointer, self.metadata_pointer
+        )
+    }
+}
+
+/// The pod metadata and spec of an object, being either a bare pod or a workload embedding a pod template
+pub(crate) struct PodTemplate<'a> {
+    obj: &'a DynamicObject,
+    /// JSON pointer of the pod's spec
+    spec_pointer: String,
+    /// JSON pointer of the pod's metadata, being the object's own metadata for `/metadata`
+    metadata_pointer: String,
+}
+
+impl<'a> PodTemplate<'a> {
+    /// Locates the pod template based on the object's type, preferring the configured locators over the built-in
+    /// workloads, returning `None` for unsupported types
+    pub(crate) fn locate(
+        obj: &'a DynamicObject,
+        locators: &[PodTemplateLocator],
+    ) -> Option<PodTemplate<'a>> {
+        // Admission requests always carry the object's type, yet fall back to a bare pod
+        let (api_version, kind) = obj.types.as_ref().map_or(("v1", "Pod"), |types| {
+            (types.api_version.as_str(), types.kind.as_str())
+        });
+
+        if let Some(locator) = locators
+            .iter()
+            .find(|locator| locator.api_version == api_version && locator.kind == kind)
+        {
+            return Some(PodTemplate {
+                obj,
+                spec_pointer: locator.spec_pointer.clone(),
+                metadata_pointer: locator.metadata_pointer.clone(),
+            });
+        }
+
+        BUILT_IN_TEMPLATES
+            .iter()
+            .find(|(built_in_api_version, built_in_kind, _)| {
+                *built_in_api_version == api_version && *built_in_kind == kind
+            })
+            .map(|(_, _, pointer)| PodTemplate {
+                obj,
+                spec_pointer: format!("{pointer}/spec"),
+                metadata_pointer: format!("{pointer}/metadata"),
+            })
+    }
+
+    /// JSON pointer of the pod's metadata
+    pub(crate) fn metadata_pointer(&self) -> &str {
+        &self.metadata_pointer
+    }
+
+    /// JSON pointer of the pod's spec
+    pub(crate) fn spec_pointer(&self) -> &str {
+        &self.spec_pointer
+    }
+
+    pub(crate) fn spec(&self) -> Option<&'a serde_json::Value> {
+        self.obj.data.pointer(&self.spec_pointer)
+    }
+
+    /// Whether the template uses the object's own metadata, which isn't part of its data
+    fn is_object_metadata(&self) -> bool {
+        self.metadata_pointer == "/metadata"
+    }
+
+    /// Whether the pod's metadata exists, which is always the case for bare pods
+    pub(crate) fn has_metadata(&self) -> bool {
+        self.is_object_metadata() || self.obj.data.pointer(&self.metadata_pointer).is_some()
+    }
+
+    /// Whether the pod's metadata contains an annotations object
+    pub(crate) fn has_annotations(&self) -> bool {
+        if self.is_object_metadata() {
+            self.obj.metadata.annotations.is_some()
+        } else {
+            self.obj
+                .data
+                .pointer(&format!("{}/annotations", self.metadata_pointer))
+                .is_some_and(|annotations| annotations.is_object())
+        }
+    }
+
+    pub(crate) fn annotations(&self) -> BTreeMap<String, String> {
+        if self.is_object_metadata() {
+            self.obj.annotations().clone()
+        } else {
+            self.metadata_map("annotations")
+        }
+    }
+
+    /// Labels of the pod, which the pods created from a template inherit
+    pub(crate) fn labels(&self) -> BTreeMap<String, String> {
+        if self.is_object_metadata() {
+            self.obj.labels().clone()
+        } else {
+            self.metadata_map("labels")
+        }
+    }
+
+    fn metadata_map(&self, field: &str) -> BTreeMap<String, String> {
+        self.obj
+            .data
+            .pointer(&format!("{}/{field}", self.metadata_pointer))
+            .and_then(|map| serde_json::from_value(map.clone()).ok())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(value: serde_json::Value) -> DynamicObject {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_locate_pod() {
+        let obj = object(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": { "name": "pod", "annotations": { "a": "b" } },
+            "spec": { "containers": [] },
+        }));
+        let template = PodTemplate::locate(&obj, &[]).unwrap();
+
+        assert_eq!(template.metadata_pointer(), "/metadata");
+        assert_eq!(template.spec_pointer(), "/spec");
+        assert_eq!(
+            template.spec(),
+            Some(&serde_json::json!({ "containers": [] }))
+        );
+        assert!(template.has_metadata());
+        assert!(template.has_annotations());
+        assert_eq!(template.annotations().get("a").unwrap(), "b");
+    }
+
+    #[test]
+    fn test_locate_deployment() {
+        let obj = object(serde_json::json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": {
+                "name": "deployment",
+                "labels": { "e": "f" },
+                "annotations": { "a": "b" },
+            },
+            "spec": {
+                "template": {
+                    "metadata": { "labels": { "g": "h" }, "annotations": { "c": "d" } },
+                    "spec": { "containers": [] },
+                },
+            },
+        }));
+        let template = PodTemplate::locate(&obj, &[]).unwrap();
+
+        assert_eq!(template.metadata_pointer(), "/spec/template/metadata");
+        assert_eq!(template.spec_pointer(), "/spec/template/spec");
+        assert!(template.spec().is_some());
+        assert!(template.has_annotations());
+        assert_eq!(template.annotations().get("a"), None);
+        assert_eq!(template.annotations().get("c").unwrap(), "d");
+        assert_eq!(template.labels().get("e"), None);
+        assert_eq!(template.labels().get("g").unwrap(), "h");
+    }
+
+    #[test]
+    fn test_locate_cron_job_without_metadata() {
+        let obj = object(serde_json::json!({
+            "apiVersion": "batch/v1",
+            "kind": "CronJob",
+            "metadata": { "name": "cron-job" },
+            "spec": {
+                "jobTemplate": { "spec": { "template": { "spec": { "containers": [] } } } },
+            },
+        }));
+        let template = PodTemplate::locate(&obj, &[]).unwrap();
+
+        assert_eq!(
+            template.spec_pointer(),
+            "/spec/jobTemplate/spec/template/spec"
+        );
+        assert!(template.spec().is_some());
+        assert!(!template.has_metadata());
+        assert!(!template.has_annotations());
+        assert!(template.annotations().is_empty());
+    }
+
+    #[test]
+    fn test_locate_pod_template() {
+        let obj = object(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "PodTemplate",
+            "metadata": { "name": "pod-template" },
+            "template": { "metadata": {}, "spec": { "containers": [] } },
+        }));
+        let template = PodTemplate::locate(&obj, &[]).unwrap();
+
+        assert_eq!(template.spec_pointer(), "/template/spec");
+        assert!(template.spec().is_some());
+        assert!(template.has_metadata());
+        assert!(!template.has_annotations());
+    }
+
+    #[test]
+    fn test_locate_unsupported_kind() {
+        let obj = object(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": "config-map" },
+        }));
+
+        assert!(PodTemplate::locate(&obj, &[]).is_none());
+    }
+
+    #[test]
+    fn test_locate_built_in_kind_of_other_group() {
+        let obj = object(serde_json::json!({
+            "apiVersion": "example.com/v1",
+            "kind": "Deployment",
+            "metadata": { "name": "deployment" },
+        }));
+
+        assert!(PodTemplate::locate(&obj, &[]).is_none());
+    }
+
+    #[test]
+    fn test_locate_custom_resource() {
+        let obj = object(serde_json::json!({
+            "apiVersion": "serving.knative.dev/v1",
+            "kind": "Service",
+            "metadata": { "name": "service" },
+            "spec": {
+                "template": {
+                    "metadata": { "annotations": { "a": "b" } },
+                    "spec": { "containers": [] },
+                },
+            },
+        }));
+        let locators = [
+            "serving.knative.dev/v1/Service=/spec/template/spec:/spec/template/metadata"
+                .parse()
+                .unwrap(),
+        ];
+        let template = PodTemplate::locate(&obj, &locators).unwrap();
+
+        assert_eq!(template.spec_pointer(), "/spec/template/spec");
+        assert!(template.spec().is_some());
+        assert_eq!(template.annotations().get("a").unwrap(), "b");
+    }
+
+    #[test]
+    fn test_locate_custom_resource_with_object_metadata() {
+        let obj = object(serde_json::json!({
+            "apiVersion": "example.com/v1",
+            "kind": "Sandbox",
+            "metadata": { "name": "sandbox" },
+            "spec": { "pod": { "containers": [] } },
+        }));
+        let locators = ["example.com/v1/Sandbox=/spec/pod:/metadata"
+            .parse()
+            .unwrap()];
+        let template = PodTemplate::locate(&obj, &locators).unwrap();
+
+        assert!(template.spec().is_some());
+        assert!(template.has_metadata());
+        assert!(!template.has_annotations());
+    }
+
+    #[test]
+    fn test_parse_locator() {
+        let locator: PodTemplateLocator =
+            "argoproj.io/v1alpha1/Rollout=/spec/template/spec:/spec/template/metadata"
+                .parse()
+                .unwrap();
+
+        assert_eq!(locator.api_version, "argoproj.io/v1alpha1");
+        assert_eq!(locator.kind, "Rollout");
+        assert_eq!(
+            locator.to_string(),
+            "argoproj.io/v1alpha1/Rollout=/spec/template/spec:/spec/template/metadata"
+        );
+    }
+
+    #[test]
+    fn test_parse_core_locator() {
+        let locator: PodTemplateLocator = "v1/Sandbox=/spec:/metadata".parse().unwrap();
+
+        assert_eq!(locator.api_version, "v1");
+        assert_eq!(locator.kind, "Sandbox");
+    }
+
+    #[test]
+    fn test_parse_invalid_locators() {
+        for locator in [
+            "",
+            "argoproj.io/v1alpha1/Rollout",
+            "Rollout=/spec/template/spec:/spec/template/metadata",
+            "argoproj.io/v1alpha1/Rollout=/spec/template/spec",
+            "argoproj.io/v1alpha1/Rollout=spec:/spec/template/metadata",
+            "argoproj.io/v1alpha1/=/spec/template/spec:/spec/template/metadata",
+        ] {
+            assert!(
+                locator.parse::<PodTemplateLocator>().is_err(),
+                "{locator:?} should be rejected"
+            );
+        }
+    }
+}
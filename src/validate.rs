@@ -0,0 +1,249 @@
+use color_eyre::{eyre::eyre, Result};
+use kube::core::DynamicObject;
+
+use crate::{
+    mutate::{declared_bandwidth, BandwidthProps, ExtraResource},
+    utils::{
+        networks::network_resource,
+        quantity::{Quantity, QuantityError},
+        template::PodTemplate,
+    },
+};
+
+/// Checks a pod's bandwidth, burst, packet rate, and per-network bandwidth resources, failing with a message naming
+/// every malformed, non-positive, or contradicting value
+pub(crate) fn validate_bandwidth(obj: &DynamicObject, props: &BandwidthProps) -> Result<()> {
+    // Objects without a pod template have nothing to validate
+    let Some(template) = PodTemplate::locate(obj, &props.pod_template_locators) else {
+        return Ok(());
+    };
+
+    let mut violations = Vec::new();
+    let spec = template.spec();
+
+    for field in ["initContainers", "containers"] {
+        let Some(containers) = spec
+            .and_then(|spec| spec.get(field))
+            .and_then(|containers| containers.as_array())
+        else {
+            continue;
+        };
+
+        for container in containers {
+            let Some(resources) = container.get("resources") else {
+                continue;
+            };
+
+            let name = container
+                .get("name")
+                .and_then(|name| name.as_str())
+                .unwrap_or_default();
+
+            validate_resources(
+                resources,
+                &format!("container \"{name}\""),
+                props,
+                &mut violations,
+            );
+        }
+    }
+
+    if let Some(resources) = spec.and_then(|spec| spec.get("resources")) {
+        validate_resources(resources, "pod resources", props, &mut violations);
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!(
+            "Invalid bandwidth resources: {}",
+            violations.join("; ")
+        ))
+    }
+}
+
+fn validate_resources(
+    resources: &serde_json::Value,
+    subject: &str,
+    props: &BandwidthProps,
+    violations: &mut Vec<String>,
+) {
+    // Each key, including deprecated aliases, is validated on its own, along with the extra and per-network resources
+    let mut keys = props.bandwidth_resource_keys().concat();
+    keys.extend(ExtraResource::ALL.map(|resource| resource.resource_key(props)));
+
+    for list in ["requests", "limits"] {
+        let Some(resource_list) = resources.get(list).and_then(|list| list.as_object()) else {
+            continue;
+        };
+
+        for key in resource_list.keys() {
+            if network_resource(key, &props.network_bandwidth_resource_prefix).is_some()
+                && !keys.contains(&key.as_str())
+            {
+                keys.push(key);
+            }
+        }
+    }
+
+    let declared = |list: &str| {
+        resources
+            .get(list)
+            .map(|resource_list| declared_bandwidth(resource_list, &keys))
+            .unwrap_or_else(|| vec![None; keys.len()])
+    };
+
+    for ((key, request), limit) in keys
+        .iter()
+        .zip(declared("requests"))
+        .zip(declared("limits"))
+    {
+        let request = validate_quantity(subject, "requests", key, request, violations);
+        let limit = validate_quantity(subject, "limits", key, limit, violations);
+
+        if let (Some(request), Some(limit)) = (request, limit) {
+            if request > limit {
+                violations.push(format!(
+                    "{subject}: request of {key:?} ({request}) exceeds its limit ({limit})"
+                ));
+            }
+        }
+    }
+}
+
+fn validate_quantity(
+    subject: &str,
+    list: &str,
+    key: &str,
+    quantity: Option<Result<Quantity, QuantityError>>,
+    violations: &mut Vec<String>,
+) -> Option<Quantity> {
+    match quantity? {
+        Ok(quantity) if quantity > Quantity::ZERO => Some(quantity),
+        Ok(quantity) => {
+            violations.push(format!(
+                "{subject}: {list} of {key:?} must be positive, got {quantity}"
+            ));
+
+            None
+        }
+        Err(err) => {
+            violations.push(format!("{subject}: {list} of {key:?} is malformed: {err}"));
+
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{controller::NamespaceCache, mutate::BandwidthMode};
+
+    fn props() -> BandwidthProps {
+        BandwidthProps::with_defaults(
+            BandwidthMode::Annotate,
+            NamespaceCache::with_namespaces(Vec::new(), true),
+        )
+    }
+
+    fn pod(resources: serde_json::Value) -> DynamicObject {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": { "name": "pod" },
+            "spec": { "containers": [{ "name": "my-container", "resources": resources }] },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate_valid_bandwidth() {
+        let obj = pod(serde_json::json!({
+            "requests": { "networking.k8s.io/egress-bandwidth": "1M" },
+            "limits": { "networking.k8s.io/egress-bandwidth": "2M" },
+        }));
+
+        assert!(validate_bandwidth(&obj, &props()).is_ok());
+    }
+
+    #[test]
+    fn test_validate_malformed_bandwidth() {
+        let obj = pod(serde_json::json!({
+            "requests": { "networking.k8s.io/egress-bandwidth": "1Kb" },
+        }));
+
+        assert_eq!(
+            validate_bandwidth(&obj, &props()).unwrap_err().to_string(),
+            "Invalid bandwidth resources: container \"my-container\": requests of \"networking.k8s.io/egress-bandwidth\" is malformed: unknown suffix \"Kb\" at byte 1"
+        );
+    }
+
+    #[test]
+    fn test_validate_non_positive_bandwidth() {
+        let obj = pod(serde_json::json!({
+            "requests": { "networking.k8s.io/ingress-bandwidth": "0" },
+            "limits": { "networking.k8s.io/egress-bandwidth": "-1M" },
+        }));
+
+        assert_eq!(
+            validate_bandwidth(&obj, &props()).unwrap_err().to_string(),
+            "Invalid bandwidth resources: container \"my-container\": limits of \"networking.k8s.io/egress-bandwidth\" must be positive, got -1M; container \"my-container\": requests of \"networking.k8s.io/ingress-bandwidth\" must be positive, got 0"
+        );
+    }
+
+    #[test]
+    fn test_validate_request_exceeding_limit() {
+        let obj = pod(serde_json::json!({
+            "requests": { "networking.k8s.io/egress-bandwidth": "2M" },
+            "limits": { "networking.k8s.io/egress-bandwidth": "1M" },
+        }));
+
+        assert_eq!(
+            validate_bandwidth(&obj, &props()).unwrap_err().to_string(),
+            "Invalid bandwidth resources: container \"my-container\": request of \"networking.k8s.io/egress-bandwidth\" (2M) exceeds its limit (1M)"
+        );
+    }
+
+    #[test]
+    fn test_validate_pod_resources() {
+        let mut obj = pod(serde_json::json!({}));
+        obj.data["spec"]["resources"] = serde_json::json!({
+            "limits": { "networking.k8s.io/egress-bandwidth": "0" },
+        });
+
+        assert_eq!(
+            validate_bandwidth(&obj, &props()).unwrap_err().to_string(),
+            "Invalid bandwidth resources: pod resources: limits of \"networking.k8s.io/egress-bandwidth\" must be positive, got 0"
+        );
+    }
+
+    #[test]
+    fn test_validate_extra_resources() {
+        let obj = pod(serde_json::json!({
+            "requests": { "networking.k8s.io/ingress-packet-rate": "0" },
+            "limits": { "networking.k8s.io/egress-burst": "10Mb" },
+        }));
+
+        assert_eq!(
+            validate_bandwidth(&obj, &props()).unwrap_err().to_string(),
+            "Invalid bandwidth resources: container \"my-container\": limits of \"networking.k8s.io/egress-burst\" is malformed: unknown suffix \"Mb\" at byte 2; container \"my-container\": requests of \"networking.k8s.io/ingress-packet-rate\" must be positive, got 0"
+        );
+    }
+
+    #[test]
+    fn test_validate_network_resources() {
+        let obj = pod(serde_json::json!({
+            "requests": { "bandwidth.nbam.io/storage-net-egress": "2G" },
+            "limits": {
+                "bandwidth.nbam.io/storage-net-egress": "1G",
+                "bandwidth.nbam.io/metrics-net-ingress": "-1M",
+            },
+        }));
+
+        assert_eq!(
+            validate_bandwidth(&obj, &props()).unwrap_err().to_string(),
+            "Invalid bandwidth resources: container \"my-container\": request of \"bandwidth.nbam.io/storage-net-egress\" (2G) exceeds its limit (1G); container \"my-container\": limits of \"bandwidth.nbam.io/metrics-net-ingress\" must be positive, got -1M"
+        );
+    }
+}